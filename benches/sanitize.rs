@@ -0,0 +1,132 @@
+//! Compares [`rla::sanitize::clean`] with the regex based pipeline it replaced, on stored logs,
+//! and measures [`rla::sanitize::read_lines`], through which the logs are processed.
+//!
+//! Run with `cargo bench --bench sanitize -- <logs...>`, where the logs are files or directories
+//! of files as downloaded by `rla-offline dl`. Files ending in `.brotli` are decompressed. Without
+//! arguments, the log used by the tests is used.
+
+use regex::bytes::Regex;
+use rust_log_analyzer as rla;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 5;
+
+struct RegexPipeline {
+    whitespace: Regex,
+    control: Regex,
+}
+
+impl RegexPipeline {
+    fn new() -> Self {
+        RegexPipeline {
+            whitespace: Regex::new("(?u:\\p{White_Space})").unwrap(),
+            control: Regex::new("(?u:\\p{Control})").unwrap(),
+        }
+    }
+
+    fn clean(&self, ci: &dyn rla::ci::CiPlatform, data: &[u8]) -> Vec<u8> {
+        let data = ci.remove_timestamp_from_log_line(data);
+        let data = rla::sanitize::render_terminal(&data);
+        let data = self.whitespace.replace_all(&data, b" ".as_ref());
+        self.control.replace_all(&data, b"".as_ref()).into_owned()
+    }
+}
+
+fn main() {
+    let mut paths = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        paths.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/test_missing_line.txt"));
+    }
+
+    let logs = paths
+        .iter()
+        .flat_map(walkdir::WalkDir::new)
+        .map(Result::unwrap)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| load(entry.path()))
+        .collect::<Vec<_>>();
+    let lines = logs
+        .iter()
+        .flat_map(|log| rla::sanitize::split_lines(log))
+        .collect::<Vec<_>>();
+    let bytes = lines.iter().map(|line| line.len()).sum::<usize>();
+    println!(
+        "{} logs, {} lines, {:.1} MB",
+        logs.len(),
+        lines.len(),
+        bytes as f64 / 1e6
+    );
+
+    let ci = rla::ci::GitHubActions::new("DUMMY_TOKEN");
+    let pipeline = RegexPipeline::new();
+
+    let mismatches = lines
+        .iter()
+        .filter(|line| *rla::sanitize::clean(&ci, line) != *pipeline.clean(&ci, line))
+        .count();
+    if mismatches > 0 {
+        println!("warning: {mismatches} lines are cleaned differently");
+    }
+
+    let regex = measure(|| {
+        for line in &lines {
+            std::hint::black_box(pipeline.clean(&ci, line));
+        }
+    });
+    let scanner = measure(|| {
+        for line in &lines {
+            std::hint::black_box(rla::sanitize::clean(&ci, line));
+        }
+    });
+
+    let throughput = |time: Duration| bytes as f64 / 1e6 / time.as_secs_f64();
+    println!("regex:   {regex:?} ({:.1} MB/s)", throughput(regex));
+    println!("scanner: {scanner:?} ({:.1} MB/s)", throughput(scanner));
+    println!(
+        "speedup: {:.2}x",
+        regex.as_secs_f64() / scanner.as_secs_f64()
+    );
+
+    // Splitting the logs, leaving out the blank lines and cleaning the others.
+    let read = measure(|| {
+        for log in &logs {
+            for line in rla::sanitize::read_lines(&ci, &log[..]) {
+                std::hint::black_box(line.unwrap());
+            }
+        }
+    });
+    println!("read_lines: {read:?} ({:.1} MB/s)", throughput(read));
+}
+
+/// Returns the fastest of [`ITERATIONS`] runs of `f`.
+fn measure(f: impl Fn()) -> Duration {
+    (0..ITERATIONS)
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+fn load(path: &Path) -> Vec<u8> {
+    let file = std::fs::File::open(path).unwrap();
+    let mut data = vec![];
+    if path.extension().is_some_and(|ext| ext == "brotli") {
+        brotli::Decompressor::new(file, 4096)
+            .read_to_end(&mut data)
+            .unwrap();
+    } else {
+        std::io::BufReader::new(file)
+            .read_to_end(&mut data)
+            .unwrap();
+    }
+    data
+}
@@ -0,0 +1,80 @@
+use crate::index::IndexData;
+use crate::normalize::Normalizer;
+use std::hash::{Hash, Hasher};
+
+/// The lines of a reference log, usually the last passing run of the same job, used to suppress
+/// the lines of a failed log which were already present before the failure.
+///
+/// Only a hash of each normalized line is kept, so large logs can be used as baselines.
+#[derive(Default)]
+pub struct Baseline {
+    /// Usually the normalizer of the index, so that the lines only differing by a duration, a
+    /// hash, etc. are considered the same, as when scoring them.
+    normalizer: Normalizer,
+    lines: fnv::FnvHashSet<u64>,
+}
+
+impl Baseline {
+    /// An empty baseline, normalizing the lines with `normalizer`.
+    pub fn new(normalizer: Normalizer) -> Self {
+        Baseline {
+            normalizer,
+            lines: Default::default(),
+        }
+    }
+
+    pub fn learn<I: IndexData>(&mut self, line: &I) {
+        let hash = self.hash(line);
+        self.lines.insert(hash);
+    }
+
+    /// Whether the normalized `line` also appears in the baseline log.
+    pub fn contains<I: IndexData>(&self, line: &I) -> bool {
+        self.lines.contains(&self.hash(line))
+    }
+
+    fn hash<I: IndexData>(&self, line: &I) -> u64 {
+        let mut hasher = fnv::FnvHasher::default();
+        self.normalizer
+            .normalize(line.sanitized())
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl<I: IndexData> FromIterator<I> for Baseline {
+    fn from_iter<T: IntoIterator<Item = I>>(lines: T) -> Self {
+        let mut baseline = Baseline::default();
+        for line in lines {
+            baseline.learn(&line);
+        }
+        baseline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Sanitized;
+
+    #[test]
+    fn test_baseline_contains() {
+        let baseline = ["Compiling foo", "error: flaky network"]
+            .iter()
+            .map(Sanitized)
+            .collect::<Baseline>();
+
+        assert!(baseline.contains(&Sanitized("error: flaky network")));
+        assert!(!baseline.contains(&Sanitized("error: flaky")));
+        assert!(!baseline.contains(&Sanitized("error[E0308]: mismatched types")));
+    }
+
+    #[test]
+    fn test_baseline_normalizes_lines() {
+        let mut baseline = Baseline::new(Normalizer::default());
+        baseline.learn(&Sanitized("test foo ... ok (1.25s) in /tmp/rustc1a2b3c"));
+
+        assert!(baseline.contains(&Sanitized("test foo ... ok (3.5s) in /tmp/rustcd4e5f6")));
+        assert!(!baseline.contains(&Sanitized("test foo ... FAILED (3.5s) in /tmp/rustcd4e5f6")));
+    }
+}
@@ -26,6 +26,7 @@ pub fn cat(input: &Path, strip_control: bool, decode_utf8: bool) -> rla::Result<
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn download(
     ci: &dyn CiPlatform,
     repo: &str,
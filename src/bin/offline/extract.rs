@@ -1,42 +1,32 @@
 use crate::offline;
 
+use rla::baseline::Baseline;
+use rla::budget::Budget;
+use rla::classify::{Category, Classifier};
+use rla::extract::LineOutput;
+use rla::garbage::GarbageFilter;
 use rla::index::IndexStorage;
+use rla::sanitize::LogLine;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 use std::time::Duration;
 use std::time::Instant;
 use walkdir::WalkDir;
 
-struct Line<'a> {
-    _original: &'a [u8],
-    sanitized: Vec<u8>,
-}
-
-impl<'a> rla::index::IndexData for Line<'a> {
-    fn sanitized(&self) -> &[u8] {
-        &self.sanitized
-    }
-}
-
-fn load_lines<'a>(ci: &dyn rla::ci::CiPlatform, log: &'a [u8]) -> Vec<Line<'a>> {
-    rla::sanitize::split_lines(log)
-        .iter()
-        .map(|&line| Line {
-            _original: line,
-            sanitized: rla::sanitize::clean(ci, line),
-        })
-        .collect()
-}
-
+#[allow(clippy::too_many_arguments)]
 pub fn dir(
     ci: &dyn rla::ci::CiPlatform,
     index_file: &IndexStorage,
     src_dir: &Path,
     dst_dir: &Path,
+    output: LineOutput,
+    budget: Option<&Budget>,
+    signatures: Option<&Path>,
+    show_category: bool,
+    garbage: GarbageFilter,
 ) -> rla::Result<()> {
-    let config = rla::extract::Config::default();
-    let index = rla::Index::load(index_file)?;
+    let analyzer = Analyzer::load(index_file, signatures, garbage)?;
 
     for entry in walk_non_hidden_children(dst_dir) {
         let entry = entry?;
@@ -51,7 +41,7 @@ pub fn dir(
     let progress_every = Duration::from_secs(1);
     let mut last_print = Instant::now();
 
-    for (count, entry) in walk_non_hidden_children(&src_dir).enumerate() {
+    for (count, entry) in walk_non_hidden_children(src_dir).enumerate() {
         let entry = entry?;
 
         if entry.file_type().is_dir() {
@@ -75,15 +65,15 @@ pub fn dir(
             );
         }
 
-        let log = offline::fs::load_maybe_compressed(entry.path())?;
-        let lines = load_lines(ci, &log);
-        let blocks = rla::extract::extract(&config, &index, &lines);
+        let log = offline::fs::open_maybe_compressed(entry.path())?;
+        let (category, blocks) = analyzer.render_blocks(ci, None, log, output, budget)?;
 
         let mut out_name = entry.file_name().to_owned();
         out_name.push(".err");
 
         write_blocks_to(
             io::BufWriter::new(fs::File::create(dst_dir.join(out_name))?),
+            show_category.then_some(category),
             &blocks,
         )?;
     }
@@ -91,35 +81,244 @@ pub fn dir(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn one(
     ci: &dyn rla::ci::CiPlatform,
     index_file: &IndexStorage,
     log_file: &Path,
+    output: LineOutput,
+    budget: Option<&Budget>,
+    json: bool,
+    signatures: Option<&Path>,
+    show_category: bool,
+    garbage: GarbageFilter,
 ) -> rla::Result<()> {
-    let config = rla::extract::Config::default();
-    let index = rla::Index::load(index_file)?;
-
-    let log = offline::fs::load_maybe_compressed(log_file)?;
-    let lines = load_lines(ci, &log);
-    let blocks = rla::extract::extract(&config, &index, &lines);
+    let analyzer = Analyzer::load(index_file, signatures, garbage)?;
 
     let stdout = io::stdout();
-    write_blocks_to(stdout.lock(), &blocks)?;
+    if json {
+        let log = offline::fs::load_maybe_compressed(log_file)?;
+        let lines = rla::sanitize::load_lines(ci, &log);
+        let extraction =
+            rla::extract::Extraction::extract(&analyzer.config, &analyzer.index, &lines);
+        let category = analyzer.classifier.classify(
+            extraction
+                .blocks
+                .iter()
+                .flat_map(|block| block.lines(&lines)),
+        );
+        write_json_to(
+            stdout.lock(),
+            category,
+            &extraction,
+            &lines,
+            &analyzer.config.garbage,
+        )?;
+    } else {
+        let log = offline::fs::open_maybe_compressed(log_file)?;
+        let (category, blocks) = analyzer.render_blocks(ci, None, log, output, budget)?;
+        write_blocks_to(stdout.lock(), show_category.then_some(category), &blocks)?;
+    }
+
+    Ok(())
+}
+
+/// Like [`one`], but suppresses the lines also present in the `baseline_file`, e.g. the log of the
+/// last passing run of the same job.
+#[allow(clippy::too_many_arguments)]
+pub fn diff(
+    ci: &dyn rla::ci::CiPlatform,
+    index_file: &IndexStorage,
+    baseline_file: &Path,
+    log_file: &Path,
+    output: LineOutput,
+    budget: Option<&Budget>,
+    signatures: Option<&Path>,
+    show_category: bool,
+    garbage: GarbageFilter,
+) -> rla::Result<()> {
+    let analyzer = Analyzer::load(index_file, signatures, garbage)?;
+
+    let mut baseline = Baseline::new(analyzer.index.normalizer().clone());
+    for line in rla::sanitize::read_lines(ci, offline::fs::open_maybe_compressed(baseline_file)?) {
+        baseline.learn(&line?);
+    }
+
+    let log = offline::fs::open_maybe_compressed(log_file)?;
+    let (category, blocks) = analyzer.render_blocks(ci, Some(&baseline), log, output, budget)?;
+    write_blocks_to(
+        io::stdout().lock(),
+        show_category.then_some(category),
+        &blocks,
+    )?;
 
     Ok(())
 }
 
-fn write_blocks_to<W: Write>(mut w: W, blocks: &[Vec<&Line>]) -> rla::Result<()> {
-    let mut first = true;
+/// Returns a budget if any limit was requested, otherwise the blocks are output in full.
+pub fn budget(max_lines: Option<usize>, max_bytes: Option<usize>) -> Option<Budget> {
+    if max_lines.is_some() || max_bytes.is_some() {
+        Some(Budget::new(max_lines, max_bytes))
+    } else {
+        None
+    }
+}
 
-    for block in blocks {
-        if !first {
-            writeln!(w, "---")?;
+/// The configuration shared by the extraction commands.
+struct Analyzer {
+    config: rla::extract::Config,
+    index: rla::Index,
+    classifier: Classifier,
+}
+
+impl Analyzer {
+    fn load(
+        index_file: &IndexStorage,
+        signatures: Option<&Path>,
+        garbage: GarbageFilter,
+    ) -> rla::Result<Self> {
+        Ok(Analyzer {
+            config: rla::extract::Config {
+                garbage,
+                ..Default::default()
+            },
+            index: rla::Index::load(index_file)?,
+            classifier: match signatures {
+                Some(path) => Classifier::load(path)?,
+                None => Classifier::default(),
+            },
+        })
+    }
+
+    /// Renders the extracted blocks in log order or, when a budget is given, the most relevant
+    /// blocks fitting in it. The log is read incrementally, only keeping the extracted lines in
+    /// memory.
+    fn render_blocks(
+        &self,
+        ci: &dyn rla::ci::CiPlatform,
+        baseline: Option<&Baseline>,
+        log: impl BufRead,
+        output: LineOutput,
+        budget: Option<&Budget>,
+    ) -> rla::Result<(Category, Vec<Vec<Vec<u8>>>)> {
+        let mut read_error = None;
+        let lines = rla::sanitize::read_lines(ci, log)
+            .map_while(|line| line.map_err(|e| read_error = Some(e)).ok());
+
+        let mut extractor = rla::extract::StreamingExtractor::new(&self.config, &self.index);
+        if let Some(baseline) = baseline {
+            extractor = extractor.with_baseline(baseline);
         }
-        first = false;
 
-        for &line in block {
-            w.write_all(&line.sanitized)?;
+        let result = match budget {
+            Some(budget) => {
+                let blocks = extractor.blocks(lines).ranked();
+                let category = self
+                    .classifier
+                    .classify(blocks.iter().flat_map(|block| &block.lines));
+                let rendered = budget
+                    .apply(&blocks, output, &self.config.garbage)
+                    .into_iter()
+                    .map(|block| block.lines)
+                    .collect();
+                (category, rendered)
+            }
+            None => {
+                let blocks = extractor.blocks(lines).collect::<Vec<_>>();
+                let category = self
+                    .classifier
+                    .classify(blocks.iter().flat_map(|block| &block.lines));
+                let rendered = blocks
+                    .iter()
+                    .map(|block| {
+                        block
+                            .lines
+                            .iter()
+                            .map(|line| output.render(line, &self.config.garbage))
+                            .collect()
+                    })
+                    .collect();
+                (category, rendered)
+            }
+        };
+
+        match read_error {
+            Some(e) => Err(e.into()),
+            None => Ok(result),
+        }
+    }
+}
+
+/// Writes the extraction as JSON, with the line indices mapped to the (1-based) line numbers of
+/// the original log.
+fn write_json_to<W: Write>(
+    w: W,
+    category: Category,
+    extraction: &rla::extract::Extraction,
+    lines: &[LogLine],
+    garbage: &GarbageFilter,
+) -> rla::Result<()> {
+    let blocks = extraction
+        .blocks
+        .iter()
+        .map(|block| {
+            let rla::extract::Trigger::UniqueLine { line, score } = block.trigger;
+            serde_json::json!({
+                "start_line": lines[block.start].number,
+                "end_line": lines[block.end - 1].number,
+                "trigger": {
+                    "rule": "unique_line",
+                    "line": lines[line].number,
+                    "score": score,
+                },
+                "lines": block
+                    .lines(lines)
+                    .iter()
+                    .zip(&block.scores)
+                    .map(|(line, score)| serde_json::json!({
+                        "number": line.number,
+                        "score": score,
+                        "text": String::from_utf8_lossy(&LineOutput::Sanitized.render(line, garbage)),
+                        "original": String::from_utf8_lossy(&LineOutput::Original.render(line, garbage)),
+                    }))
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let diagnostics = extraction
+        .blocks
+        .iter()
+        .flat_map(|block| rla::diagnostics::parse(block.lines(lines)))
+        .collect::<Vec<_>>();
+
+    serde_json::to_writer_pretty(
+        w,
+        &serde_json::json!({
+            "category": category,
+            "diagnostics": diagnostics,
+            "annotations": extraction.annotations,
+            "blocks": blocks,
+        }),
+    )?;
+    Ok(())
+}
+
+/// Writes the `blocks`, each preceded by a `---` line, optionally after a `category: ...` line.
+fn write_blocks_to<W: Write>(
+    mut w: W,
+    category: Option<Category>,
+    blocks: &[Vec<Vec<u8>>],
+) -> rla::Result<()> {
+    if let Some(category) = category {
+        writeln!(w, "category: {}", category)?;
+    }
+
+    for block in blocks {
+        writeln!(w, "---")?;
+
+        for line in block {
+            w.write_all(line)?;
             w.write_all(b"\n")?;
         }
     }
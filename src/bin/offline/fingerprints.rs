@@ -0,0 +1,31 @@
+use rla::fingerprint::{self, FingerprintStore};
+use std::path::Path;
+
+/// Number of PRs listed for each recurring failure.
+const MAX_LISTED_PRS: usize = 5;
+
+/// Prints the `count` failures seen the most often by the server.
+pub fn fingerprints(store: &Path, count: usize) -> rla::Result<()> {
+    let store = FingerprintStore::load(store, fingerprint::DEFAULT_MAX_AGE.as_secs())?;
+
+    for (fingerprint, entry) in store.top(count) {
+        println!(
+            "{:>5} {} {}",
+            entry.occurrences.len(),
+            &fingerprint.0[..12],
+            entry.sample
+        );
+
+        let mut prs = vec![];
+        for occurrence in entry.occurrences.iter().rev() {
+            let pr = format!("{}#{}", occurrence.repo, occurrence.pr);
+            if !prs.contains(&pr) {
+                prs.push(pr);
+            }
+        }
+        prs.truncate(MAX_LISTED_PRS);
+        println!("      seen in {}", prs.join(", "));
+    }
+
+    Ok(())
+}
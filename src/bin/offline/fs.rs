@@ -1,7 +1,7 @@
 use anyhow::Context;
 use percent_encoding::{AsciiSet, CONTROLS};
 use std::fs;
-use std::io::{Read, Write};
+use std::io::{self, BufRead, Read, Write};
 use std::path::Path;
 
 const BROTLI_BUFFER: usize = 4096;
@@ -47,7 +47,7 @@ pub fn load_compressed(inp: &Path) -> rla::Result<Vec<u8>> {
 }
 
 pub fn load_maybe_compressed(inp: &Path) -> rla::Result<Vec<u8>> {
-    if inp.extension().map_or(false, |e| e == "brotli") {
+    if inp.extension().is_some_and(|e| e == "brotli") {
         load_compressed(inp)
     } else {
         let mut buf = vec![];
@@ -56,6 +56,20 @@ pub fn load_maybe_compressed(inp: &Path) -> rla::Result<Vec<u8>> {
     }
 }
 
+/// Like [`load_maybe_compressed`], but returns a reader instead of loading the whole file in
+/// memory.
+pub fn open_maybe_compressed(inp: &Path) -> rla::Result<Box<dyn BufRead>> {
+    let file = fs::File::open(inp)?;
+    if inp.extension().is_some_and(|e| e == "brotli") {
+        Ok(Box::new(io::BufReader::new(brotli::Decompressor::new(
+            file,
+            BROTLI_BUFFER,
+        ))))
+    } else {
+        Ok(Box::new(io::BufReader::new(file)))
+    }
+}
+
 pub(crate) fn encode_path(path: &str) -> String {
     percent_encoding::percent_encode(path.as_bytes(), &FILENAME_ENCODE_SET).collect::<String>()
 }
@@ -1,7 +1,7 @@
 use crate::offline;
 
 use rla::index::IndexStorage;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use std::time::Instant;
 use walkdir::WalkDir;
@@ -11,8 +11,15 @@ pub fn learn(
     index_file: &IndexStorage,
     inputs: &[PathBuf],
     multiplier: u32,
+    normalization_rules: Option<&Path>,
 ) -> rla::Result<()> {
-    let mut index = rla::Index::load_or_create(index_file)?;
+    let mut index = match normalization_rules {
+        Some(path) => {
+            rla::Index::load_or_create(index_file, rla::normalize::Normalizer::load(path)?)?
+        }
+        // Existing indexes keep the rules they were built with, even if they predate them.
+        None => rla::Index::load_or_default(index_file)?,
+    };
 
     let progress_every = Duration::from_secs(1);
     let mut last_print = Instant::now();
@@ -38,11 +45,8 @@ pub fn learn(
 
         let data = offline::fs::load_maybe_compressed(input.path())?;
 
-        for line in rla::sanitize::split_lines(&data) {
-            index.learn(
-                &rla::index::Sanitized(rla::sanitize::clean(ci, line)),
-                multiplier,
-            );
+        for line in rla::sanitize::load_lines(ci, &data) {
+            index.learn(&line, multiplier);
         }
     }
 
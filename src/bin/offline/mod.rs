@@ -1,7 +1,9 @@
 pub mod dl;
 pub mod extract;
 
+pub use self::fingerprints::fingerprints;
 pub use self::learn::learn;
 
+mod fingerprints;
 mod fs;
 mod learn;
@@ -12,6 +12,7 @@ extern crate rust_log_analyzer as rla;
 extern crate walkdir;
 
 use clap::Parser;
+use rla::extract::LineOutput;
 use rla::index::IndexStorage;
 use std::path::PathBuf;
 
@@ -62,6 +63,11 @@ enum Cli {
             help = "A multiplier to apply when learning."
         )]
         multiplier: u32,
+        #[arg(
+            long = "normalization-rules",
+            help = "JSON file with additional rules normalizing the lines, applied before the built-in ones. Must match the rules of an existing index, whose own rules are used otherwise."
+        )]
+        normalization_rules: Option<PathBuf>,
         #[arg(
             help = "The log files to learn from.\nDirectories are traversed recursively. Hidden files are ignore."
         )]
@@ -93,6 +99,34 @@ enum Cli {
             help = "The directory in which to write the results. All non-hidden will be deleted from the directory."
         )]
         dest: PathBuf,
+        #[arg(
+            long = "lines",
+            default_value = "sanitized",
+            help = "Which version of the lines to output: sanitized, original or both."
+        )]
+        lines: LineOutput,
+        #[arg(
+            long = "max-lines",
+            help = "Maximum number of lines to output, keeping the most relevant blocks."
+        )]
+        max_lines: Option<usize>,
+        #[arg(
+            long = "max-bytes",
+            help = "Maximum number of bytes to output, keeping the most relevant blocks."
+        )]
+        max_bytes: Option<usize>,
+        #[arg(
+            long = "signatures",
+            help = "JSON file with additional failure signatures, used to classify the failure."
+        )]
+        signatures: Option<PathBuf>,
+        #[arg(
+            long = "show-category",
+            help = "Output the category of the failure before the extracted blocks."
+        )]
+        show_category: bool,
+        #[command(flatten)]
+        garbage: util::GarbageArgs,
     },
 
     #[command(
@@ -108,10 +142,115 @@ enum Cli {
             help = "The index file to read / write."
         )]
         index_file: IndexStorage,
+        #[arg(
+            long = "json",
+            conflicts_with_all = ["lines", "max_lines", "max_bytes", "show_category"],
+            help = "Output all the extracted blocks as JSON, including line numbers, scores and both versions of the lines."
+        )]
+        json: bool,
+        #[arg(
+            long = "lines",
+            default_value = "sanitized",
+            help = "Which version of the lines to output: sanitized, original or both."
+        )]
+        lines: LineOutput,
+        #[arg(
+            long = "max-lines",
+            help = "Maximum number of lines to output, keeping the most relevant blocks."
+        )]
+        max_lines: Option<usize>,
+        #[arg(
+            long = "max-bytes",
+            help = "Maximum number of bytes to output, keeping the most relevant blocks."
+        )]
+        max_bytes: Option<usize>,
+        #[arg(
+            long = "signatures",
+            help = "JSON file with additional failure signatures, used to classify the failure."
+        )]
+        signatures: Option<PathBuf>,
+        #[arg(
+            long = "show-category",
+            help = "Output the category of the failure before the extracted blocks."
+        )]
+        show_category: bool,
+        #[command(flatten)]
+        garbage: util::GarbageArgs,
         #[arg(help = "The log file to analyze.")]
         log: PathBuf,
     },
 
+    #[command(
+        name = "extract-diff",
+        about = "Extract a potential error message from a single log file, ignoring the lines also present in a baseline log."
+    )]
+    ExtractDiff {
+        #[arg(long = "ci", help = "CI platform to download from.")]
+        ci: util::CliCiPlatform,
+        #[arg(
+            short = 'i',
+            long = "index-file",
+            help = "The index file to read / write."
+        )]
+        index_file: IndexStorage,
+        #[arg(
+            short = 'b',
+            long = "baseline",
+            help = "The reference log, e.g. the last passing run of the same job."
+        )]
+        baseline: PathBuf,
+        #[arg(
+            long = "lines",
+            default_value = "sanitized",
+            help = "Which version of the lines to output: sanitized, original or both."
+        )]
+        lines: LineOutput,
+        #[arg(
+            long = "max-lines",
+            help = "Maximum number of lines to output, keeping the most relevant blocks."
+        )]
+        max_lines: Option<usize>,
+        #[arg(
+            long = "max-bytes",
+            help = "Maximum number of bytes to output, keeping the most relevant blocks."
+        )]
+        max_bytes: Option<usize>,
+        #[arg(
+            long = "signatures",
+            help = "JSON file with additional failure signatures, used to classify the failure."
+        )]
+        signatures: Option<PathBuf>,
+        #[arg(
+            long = "show-category",
+            help = "Output the category of the failure before the extracted blocks."
+        )]
+        show_category: bool,
+        #[command(flatten)]
+        garbage: util::GarbageArgs,
+        #[arg(help = "The log file to analyze.")]
+        log: PathBuf,
+    },
+
+    #[command(
+        name = "fingerprints",
+        about = "List the failures recorded the most often by the server."
+    )]
+    Fingerprints {
+        #[arg(
+            short = 's',
+            long = "store",
+            help = "The fingerprint store written by the server."
+        )]
+        store: PathBuf,
+        #[arg(
+            short = 'c',
+            long = "count",
+            default_value = "10",
+            help = "Number of failures to list."
+        )]
+        count: usize,
+    },
+
     #[command(name = "dl", about = "Download build logs from the CI platform.")]
     Dl {
         #[arg(long = "ci", help = "CI platform to download from.")]
@@ -156,19 +295,82 @@ fn main() {
             ci,
             index_file,
             multiplier,
+            normalization_rules,
             logs,
-        } => offline::learn(ci.get()?.as_ref(), &index_file, &logs, multiplier),
+        } => offline::learn(
+            ci.get()?.as_ref(),
+            &index_file,
+            &logs,
+            multiplier,
+            normalization_rules.as_deref(),
+        ),
         Cli::ExtractDir {
             ci,
             index_file,
             source,
             dest,
-        } => offline::extract::dir(ci.get()?.as_ref(), &index_file, &source, &dest),
+            lines,
+            max_lines,
+            max_bytes,
+            signatures,
+            show_category,
+            garbage,
+        } => offline::extract::dir(
+            ci.get()?.as_ref(),
+            &index_file,
+            &source,
+            &dest,
+            lines,
+            offline::extract::budget(max_lines, max_bytes).as_ref(),
+            signatures.as_deref(),
+            show_category,
+            garbage.filter(),
+        ),
         Cli::ExtractOne {
             ci,
             index_file,
+            json,
+            lines,
+            max_lines,
+            max_bytes,
+            signatures,
+            show_category,
+            garbage,
+            log,
+        } => offline::extract::one(
+            ci.get()?.as_ref(),
+            &index_file,
+            &log,
+            lines,
+            offline::extract::budget(max_lines, max_bytes).as_ref(),
+            json,
+            signatures.as_deref(),
+            show_category,
+            garbage.filter(),
+        ),
+        Cli::ExtractDiff {
+            ci,
+            index_file,
+            baseline,
+            lines,
+            max_lines,
+            max_bytes,
+            signatures,
+            show_category,
+            garbage,
             log,
-        } => offline::extract::one(ci.get()?.as_ref(), &index_file, &log),
+        } => offline::extract::diff(
+            ci.get()?.as_ref(),
+            &index_file,
+            &baseline,
+            &log,
+            lines,
+            offline::extract::budget(max_lines, max_bytes).as_ref(),
+            signatures.as_deref(),
+            show_category,
+            garbage.filter(),
+        ),
+        Cli::Fingerprints { store, count } => offline::fingerprints(&store, count),
         Cli::Dl {
             ci,
             repo,
@@ -14,9 +14,7 @@ extern crate regex;
 extern crate rust_log_analyzer as rla;
 extern crate serde_json;
 
-use crate::server::QueueItem;
 use clap::Parser;
-use crossbeam::channel::Sender;
 use rla::index::IndexStorage;
 use std::process;
 use std::sync::Arc;
@@ -56,6 +54,19 @@ struct Cli {
         help = "Post all comments to the given issue instead of the actual PR. Format: \"user/repo#id\""
     )]
     debug_post: Option<String>,
+    #[arg(
+        long = "lines",
+        default_value = "sanitized",
+        help = "Which version of the log lines to post: sanitized, original or both."
+    )]
+    lines: rla::extract::LineOutput,
+    #[arg(
+        long = "signatures",
+        help = "JSON file with additional failure signatures, used to classify the failures."
+    )]
+    signatures: Option<std::path::PathBuf>,
+    #[command(flatten)]
+    garbage: util::GarbageArgs,
     #[arg(
         long = "webhook-verify",
         help = "If enabled, web hooks that cannot be verified are rejected."
@@ -76,6 +87,75 @@ struct Cli {
         help = "Always query builds from the primary repo instead of the repo receiving them."
     )]
     query_builds_from_primary_repo: bool,
+    #[arg(
+        long = "baseline-branch",
+        help = "Ignore the log lines also present in the same job of the latest passed build on this branch."
+    )]
+    baseline_branch: Option<String>,
+    #[arg(
+        long = "spurious-label",
+        help = "Label to add to the PR along with the comment when the failure looks spurious."
+    )]
+    spurious_label: Option<String>,
+    #[arg(
+        long = "fingerprint-store",
+        help = "File in which to remember the recent failures, to report recurring ones."
+    )]
+    fingerprint_store: Option<std::path::PathBuf>,
+    #[arg(
+        long = "report-variable",
+        help = "Log variable (defined in the log by a `[NAME=value]` line) to show in the reports. Can be repeated."
+    )]
+    report_variables: Vec<String>,
+    #[arg(
+        long = "max-reported-jobs",
+        default_value = "3",
+        help = "Maximum number of failed jobs analyzed when a build has multiple failed jobs. The other ones are only listed."
+    )]
+    max_reported_jobs: usize,
+    #[arg(
+        long = "comment-template",
+        help = "Template of the comments reporting failed builds, replacing the default one (src/bin/server/comment.md)."
+    )]
+    comment_template: Option<std::path::PathBuf>,
+    #[arg(
+        long = "update-comments",
+        help = "Edit the previous report about the same commit of the PR instead of posting a new one, appending the newly failed jobs."
+    )]
+    update_comments: bool,
+    #[arg(
+        long = "output",
+        default_value = "comment",
+        help = "Where to post the reports: comment (on the PR), check-run (on its head commit, requires a GitHub App token) or both."
+    )]
+    output: server::ReportOutput,
+    #[arg(
+        long = "sinks",
+        help = "JSON file routing the reports to GitHub, webhooks, Zulip or files, replacing --output, --update-comments and --spurious-label."
+    )]
+    sinks: Option<std::path::PathBuf>,
+    #[arg(
+        long = "dry-run",
+        help = "Don't write anything to GitHub, and store the reports in the given directory, or JSONL file if it ends with .jsonl, instead."
+    )]
+    dry_run: Option<std::path::PathBuf>,
+    #[arg(
+        long = "queue-capacity",
+        default_value = "1000",
+        help = "Maximum number of events waiting to be processed. Further events are rejected."
+    )]
+    queue_capacity: usize,
+    #[arg(
+        long = "queue-journal",
+        help = "File in which to journal the received events, to process them after a restart."
+    )]
+    queue_journal: Option<std::path::PathBuf>,
+    #[arg(
+        long = "workers",
+        default_value = "1",
+        help = "Number of events processed at the same time, sharing the index."
+    )]
+    workers: usize,
 }
 
 #[test]
@@ -91,33 +171,63 @@ fn main() {
 
         let addr = std::net::SocketAddr::new(args.bind, args.port);
 
-        let (queue_send, queue_recv) = crossbeam::channel::unbounded();
-
-        let service = Arc::new(server::RlaService::new(
-            args.webhook_verify,
-            queue_send.clone(),
+        let queue = Arc::new(server::Queue::new(
+            args.queue_capacity,
+            args.queue_journal.as_deref(),
         )?);
 
-        let mut worker = server::Worker::new(
+        let service = Arc::new(server::RlaService::new(args.webhook_verify, queue.clone())?);
+
+        let template = match args.comment_template {
+            Some(path) => server::CommentTemplate::load(&path)?,
+            None => Default::default(),
+        };
+        let sinks = match (&args.dry_run, args.sinks) {
+            (Some(path), _) => server::Sinks::dry_run(path.clone(), &template),
+            (None, Some(path)) => server::Sinks::load(&path, &template)?,
+            (None, None) => server::Sinks::from_output(
+                args.output,
+                args.update_comments,
+                args.spurious_label,
+                &template,
+            )?,
+        };
+
+        let worker = Arc::new(server::Worker::new(
             args.index_file,
             args.debug_post,
-            queue_recv,
+            args.lines,
+            args.garbage.filter(),
+            match args.signatures {
+                Some(path) => rla::classify::Classifier::load(&path)?,
+                None => Default::default(),
+            },
+            queue.clone(),
             args.ci.get()?,
             args.repo,
             args.secondary_repos,
             args.query_builds_from_primary_repo,
-        )?;
-
-        let worker_thread = thread::spawn(move || {
-            if let Err(e) = worker.main() {
-                error!("Worker failed, exiting: {}", e);
-                process::exit(1);
-            }
-
-            info!("Work finished, exiting.");
+            args.baseline_branch,
+            args.fingerprint_store,
+            args.report_variables,
+            args.max_reported_jobs,
+            sinks,
+            args.dry_run.is_some(),
+        )?);
 
-            process::exit(0);
-        });
+        let worker_threads = (0..args.workers.max(1))
+            .map(|i| {
+                let worker = worker.clone();
+                thread::Builder::new()
+                    .name(format!("worker-{i}"))
+                    .spawn(move || {
+                        if let Err(e) = worker.main() {
+                            error!("Worker failed, exiting: {}", e);
+                            process::exit(1);
+                        }
+                    })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
 
         tokio::runtime::Runtime::new()?.block_on(async move {
             let s = service.clone();
@@ -131,17 +241,23 @@ fn main() {
                         }))
                     }
                 }))
-                .with_graceful_shutdown(graceful_shutdown(queue_send))
+                .with_graceful_shutdown(graceful_shutdown(queue))
                 .await
         })?;
 
-        worker_thread.join().expect("worker thread failed");
+        for worker_thread in worker_threads {
+            worker_thread.join().expect("worker thread failed");
+        }
+
+        worker.save_index()?;
+
+        info!("Work finished, exiting.");
 
         Ok(())
     });
 }
 
-async fn graceful_shutdown(sender: Sender<QueueItem>) {
+async fn graceful_shutdown(queue: Arc<server::Queue>) {
     let ctrl_c = tokio::signal::ctrl_c();
 
     // ECS uses SIGTERM to signal graceful shutdown must begin.
@@ -160,5 +276,5 @@ async fn graceful_shutdown(sender: Sender<QueueItem>) {
     };
 
     info!("graceful shutdown signal received");
-    let _ = sender.send(QueueItem::GracefulShutdown);
+    queue.shutdown();
 }
@@ -1,7 +1,13 @@
+pub use self::queue::Queue;
+pub use self::report::{CommentTemplate, ReportOutput};
 pub use self::service::RlaService;
+pub use self::sinks::Sinks;
 pub use self::worker::Worker;
 
+mod queue;
+mod report;
 mod service;
+mod sinks;
 mod worker;
 
 pub enum QueueItem {
@@ -21,11 +27,30 @@ pub enum QueueItem {
 }
 
 impl QueueItem {
+    /// Decodes the payload of a webhook `event` handled by the worker.
+    fn decode(event: &str, delivery_id: String, body: &[u8]) -> rla::Result<QueueItem> {
+        Ok(match event {
+            "status" => QueueItem::GitHubStatus {
+                payload: serde_json::from_slice(body)?,
+                delivery_id,
+            },
+            "check_run" => QueueItem::GitHubCheckRun {
+                payload: serde_json::from_slice(body)?,
+                delivery_id,
+            },
+            "pull_request" => QueueItem::GitHubPullRequest {
+                payload: serde_json::from_slice(body)?,
+                delivery_id,
+            },
+            other => anyhow::bail!("unexpected event: {}", other),
+        })
+    }
+
     fn delivery_id(&self) -> Option<&str> {
         match self {
-            QueueItem::GitHubStatus { delivery_id, .. } => Some(&delivery_id),
-            QueueItem::GitHubCheckRun { delivery_id, .. } => Some(&delivery_id),
-            QueueItem::GitHubPullRequest { delivery_id, .. } => Some(&delivery_id),
+            QueueItem::GitHubStatus { delivery_id, .. } => Some(delivery_id),
+            QueueItem::GitHubCheckRun { delivery_id, .. } => Some(delivery_id),
+            QueueItem::GitHubPullRequest { delivery_id, .. } => Some(delivery_id),
             QueueItem::GracefulShutdown => None,
         }
     }
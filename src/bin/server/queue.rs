@@ -0,0 +1,279 @@
+use super::worker::RecentlySeen;
+use super::QueueItem;
+
+use atomicwrites::{AtomicFile, OverwriteBehavior};
+use crossbeam::channel::{Receiver, Sender};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// How many delivery IDs are remembered to ignore the events delivered again by GitHub.
+const REMEMBERED_DELIVERIES: usize = 1024;
+/// How many processed events are appended to the journal before it's compacted.
+const COMPACTION_INTERVAL: usize = 1000;
+
+/// Why an event was not queued.
+#[derive(Debug)]
+pub enum PushError {
+    /// The event was already received.
+    Duplicate,
+    /// The queue reached its capacity.
+    Full,
+    /// The event could not be written to the journal.
+    Journal(anyhow::Error),
+}
+
+/// The events waiting to be processed by the workers, with a bounded capacity.
+///
+/// When a journal is configured, the received events are appended to it along with a record of
+/// their completion, and the events which weren't processed are queued again on startup.
+pub struct Queue {
+    sender: Sender<QueueItem>,
+    receiver: Receiver<QueueItem>,
+    capacity: usize,
+    state: Mutex<State>,
+}
+
+struct State {
+    journal: Option<Journal>,
+    /// Delivery IDs of the recently received events, as GitHub may deliver an event again.
+    recent: RecentlySeen<String>,
+}
+
+impl Queue {
+    pub fn new(capacity: usize, journal: Option<&Path>) -> rla::Result<Queue> {
+        let mut recent = RecentlySeen::new(REMEMBERED_DELIVERIES);
+        let mut journal = journal.map(Journal::open).transpose()?;
+
+        let pending = journal
+            .as_ref()
+            .map(|journal| journal.pending.iter().cloned().collect::<Vec<_>>())
+            .unwrap_or_default();
+        // Replayed events are queued even if the capacity was lowered since they were received.
+        let (sender, receiver) = crossbeam::channel::bounded(capacity.max(pending.len()) + 1);
+        for event in &pending {
+            match QueueItem::decode(
+                &event.event,
+                event.delivery_id.clone(),
+                event.body.as_bytes(),
+            ) {
+                Ok(item) => {
+                    recent.store(event.delivery_id.clone());
+                    sender.send(item)?;
+                }
+                Err(e) => {
+                    warn!(
+                        "dropping journaled event {} which can't be decoded: {}",
+                        event.delivery_id, e
+                    );
+                    // Otherwise it would be replayed on every start.
+                    if let Some(ref mut journal) = journal {
+                        journal.append(Record::Done(event.delivery_id.clone()))?;
+                    }
+                }
+            }
+        }
+        if !pending.is_empty() {
+            info!("replayed {} events from the journal", pending.len());
+        }
+
+        Ok(Queue {
+            sender,
+            receiver,
+            capacity,
+            state: Mutex::new(State { journal, recent }),
+        })
+    }
+
+    /// Queues the `item` decoded from the webhook `body`, journaling it first.
+    pub fn push(&self, event: &str, body: &[u8], item: QueueItem) -> Result<(), PushError> {
+        let delivery_id = item.delivery_id().unwrap_or_default().to_string();
+
+        let mut state = self.state.lock().unwrap();
+        if state.recent.recently_witnessed(&delivery_id) {
+            return Err(PushError::Duplicate);
+        }
+        if self.sender.len() >= self.capacity {
+            return Err(PushError::Full);
+        }
+
+        if let Some(ref mut journal) = state.journal {
+            let event = Event {
+                delivery_id: delivery_id.clone(),
+                event: event.to_string(),
+                body: String::from_utf8_lossy(body).into_owned(),
+            };
+            journal
+                .append(Record::Push(event))
+                .map_err(PushError::Journal)?;
+        }
+        state.recent.store(delivery_id);
+
+        // The channel has room for the item, as the senders hold the lock.
+        self.sender
+            .send(item)
+            .map_err(|e| PushError::Journal(e.into()))
+    }
+
+    pub fn recv(&self) -> rla::Result<QueueItem> {
+        Ok(self.receiver.recv()?)
+    }
+
+    /// Records that the event was processed, so that it's not replayed after a restart.
+    pub fn complete(&self, delivery_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(ref mut journal) = state.journal {
+            if let Err(e) = journal.append(Record::Done(delivery_id.to_string())) {
+                error!("failed to record the completion of {}: {}", delivery_id, e);
+            }
+        }
+    }
+
+    /// Asks the workers to exit once the events queued before are processed, each worker passing
+    /// the request on before exiting. Events still in the queue at that point are replayed from
+    /// the journal on the next start.
+    pub fn shutdown(&self) {
+        let _ = self.sender.send(QueueItem::GracefulShutdown);
+    }
+}
+
+/// A webhook event, as received.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Event {
+    delivery_id: String,
+    event: String,
+    body: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Record {
+    Push(Event),
+    Done(String),
+}
+
+/// Append-only file with one JSON record per line, either an event or the completion of an event.
+struct Journal {
+    path: PathBuf,
+    file: File,
+    /// The events which weren't processed yet, oldest first.
+    pending: VecDeque<Event>,
+    /// Completions appended since the last compaction.
+    completed: usize,
+}
+
+impl Journal {
+    fn open(path: &Path) -> rla::Result<Journal> {
+        let mut pending: VecDeque<Event> = VecDeque::new();
+        if path.exists() {
+            for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+                // The last line might be incomplete if the process was killed while writing it.
+                match serde_json::from_str(&line?) {
+                    Ok(Record::Push(event)) => pending.push_back(event),
+                    Ok(Record::Done(id)) => pending.retain(|event| event.delivery_id != id),
+                    Err(e) => warn!("ignoring invalid line {} of the journal: {}", number + 1, e),
+                }
+            }
+        }
+
+        let mut journal = Journal {
+            path: path.to_path_buf(),
+            // Replaced by the compaction.
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+            pending,
+            completed: 0,
+        };
+        journal.compact()?;
+        Ok(journal)
+    }
+
+    fn append(&mut self, record: Record) -> rla::Result<()> {
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+
+        match record {
+            Record::Push(event) => self.pending.push_back(event),
+            Record::Done(id) => {
+                self.pending.retain(|event| event.delivery_id != id);
+                self.completed += 1;
+                if self.completed >= COMPACTION_INTERVAL {
+                    self.compact()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrites the journal with only the pending events.
+    fn compact(&mut self) -> rla::Result<()> {
+        AtomicFile::new(&self.path, OverwriteBehavior::AllowOverwrite)
+            .write(|file| -> rla::Result<()> {
+                for event in &self.pending {
+                    serde_json::to_writer(&mut *file, &Record::Push(event.clone()))?;
+                    file.write_all(b"\n")?;
+                }
+                Ok(())
+            })
+            .map_err(|e| anyhow::anyhow!("failed to compact the journal: {}", e))?;
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        self.completed = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push(queue: &Queue, delivery_id: &str) -> Result<(), PushError> {
+        let body = br#"{"action": "synchronize", "number": 1, "repository": {"full_name": "rust-lang/rust"}}"#;
+        let item = QueueItem::decode("pull_request", delivery_id.into(), body).unwrap();
+        queue.push("pull_request", body, item)
+    }
+
+    #[test]
+    fn test_journal_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue.jsonl");
+
+        let queue = Queue::new(2, Some(&path)).unwrap();
+        push(&queue, "1").unwrap();
+        assert!(matches!(push(&queue, "1"), Err(PushError::Duplicate)));
+        push(&queue, "2").unwrap();
+        assert!(matches!(push(&queue, "3"), Err(PushError::Full)));
+
+        let item = queue.recv().unwrap();
+        assert_eq!(item.delivery_id(), Some("1"));
+        queue.complete("1");
+        drop(queue);
+
+        let queue = Queue::new(2, Some(&path)).unwrap();
+        assert_eq!(queue.recv().unwrap().delivery_id(), Some("2"));
+        assert!(matches!(push(&queue, "2"), Err(PushError::Duplicate)));
+        push(&queue, "3").unwrap();
+    }
+
+    #[test]
+    fn test_journal_invalid_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue.jsonl");
+        let event = Event {
+            delivery_id: "1".into(),
+            event: "pull_request".into(),
+            body: "not json".into(),
+        };
+        let mut line = serde_json::to_string(&Record::Push(event)).unwrap();
+        line.push('\n');
+        std::fs::write(&path, line).unwrap();
+
+        Queue::new(1, Some(&path)).unwrap();
+        let journal = Journal::open(&path).unwrap();
+        assert!(journal.pending.is_empty());
+    }
+}
@@ -0,0 +1,477 @@
+use minijinja::{context, Environment, Value};
+use rla::classify::Category;
+use rla::diagnostics::{Diagnostic, Kind};
+use rla::fingerprint::Fingerprint;
+use rla::github::{AnnotationLevel, CheckAnnotation, CheckRunOutput};
+use rla::log_variables::LogVariables;
+use serde_derive::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+
+static DEFAULT_TEMPLATE: &str = include_str!("comment.md");
+
+static MARKER_PREFIX: &str = "<!-- rla-report ";
+static MARKER_SUFFIX: &str = " -->";
+
+/// GitHub rejects check run summaries longer than this many characters.
+const MAX_SUMMARY_LENGTH: usize = 65535;
+
+/// Where the reports are posted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportOutput {
+    /// A comment on the PR.
+    Comment,
+    /// A check run on the head commit of the PR, with the recognized diagnostics as annotations.
+    CheckRun,
+    Both,
+}
+
+impl ReportOutput {
+    pub fn posts_comment(self) -> bool {
+        matches!(self, ReportOutput::Comment | ReportOutput::Both)
+    }
+
+    pub fn creates_check_run(self) -> bool {
+        matches!(self, ReportOutput::CheckRun | ReportOutput::Both)
+    }
+}
+
+impl FromStr for ReportOutput {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> rla::Result<Self> {
+        Ok(match input {
+            "comment" => ReportOutput::Comment,
+            "check-run" => ReportOutput::CheckRun,
+            "both" => ReportOutput::Both,
+            other => anyhow::bail!("unknown report output: {}", other),
+        })
+    }
+}
+
+/// How often a failure was seen recently in other PRs.
+pub struct Recurrence {
+    pub count: usize,
+    pub days: u64,
+    /// Reference to the last PR the failure was seen in, e.g. `#123`.
+    pub reference: String,
+}
+
+/// What was found in the log of a failed job.
+pub struct JobReport<'a> {
+    pub job: &'a dyn rla::ci::Job,
+    pub log_variables: LogVariables,
+    pub category: Category,
+    /// Pattern of the signature used to classify the failure, if any.
+    pub signature: Option<String>,
+    pub fingerprint: Option<Fingerprint>,
+    /// Line of the failure the fingerprint is computed from, normalized and stored along with it.
+    pub sample: Vec<u8>,
+    pub recurrence: Option<Recurrence>,
+    /// The log variables to show, with their value.
+    pub metadata: Vec<(String, String)>,
+    pub failed_tests: Vec<String>,
+    pub annotations: Vec<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Links to the lines of each rendered block in the web UI of the CI provider, if supported.
+    pub locations: Vec<Option<String>>,
+    /// The rendered blocks, the most relevant first.
+    pub blocks: Vec<String>,
+}
+
+impl JobReport<'_> {
+    pub fn name(&self) -> &str {
+        self.log_variables.job_name().unwrap_or(self.job.name())
+    }
+
+    /// The variables describing the job in the comment template and in the JSON reports.
+    pub fn context(&self) -> Value {
+        context! {
+            name => self.name(),
+            job_name => self.log_variables.job_name(),
+            html_url => self.job.html_url(),
+            log_url => self.job.log_url(),
+            log_enhanced_url => self.job.log_enhanced_url(),
+            category => self.category.to_string(),
+            spurious => self.category.is_spurious(),
+            signature => self.signature,
+            recurrence => self.recurrence.as_ref().map(|recurrence| context! {
+                count => recurrence.count,
+                days => recurrence.days,
+                reference => recurrence.reference,
+            }),
+            metadata => self
+                .metadata
+                .iter()
+                .map(|(name, value)| context! { name, value })
+                .collect::<Vec<_>>(),
+            failed_tests => self.failed_tests,
+            annotations => self.annotations,
+            diagnostics => self
+                .diagnostics
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            locations => self.locations,
+            blocks => self.blocks,
+            doc_url => self.log_variables.doc_url(),
+        }
+    }
+}
+
+/// Template of the comment reporting the failed jobs of a build, using the
+/// [minijinja](https://docs.rs/minijinja) syntax. The variables available in the template are
+/// described in the default template, `comment.md`.
+#[derive(Clone)]
+pub struct CommentTemplate {
+    source: String,
+}
+
+impl Default for CommentTemplate {
+    fn default() -> Self {
+        CommentTemplate {
+            source: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+}
+
+impl CommentTemplate {
+    pub fn new(source: String) -> rla::Result<Self> {
+        let template = CommentTemplate { source };
+        // Report syntax errors at startup rather than when posting the first comment.
+        template.environment().template_from_str(&template.source)?;
+        Ok(template)
+    }
+
+    pub fn load(path: &Path) -> rla::Result<Self> {
+        CommentTemplate::new(std::fs::read_to_string(path)?)
+    }
+
+    fn environment(&self) -> Environment<'_> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_lstrip_blocks(true);
+        env
+    }
+
+    /// Renders the comment reporting the failed jobs. `omitted` are the names of the failed jobs
+    /// which were not analyzed.
+    pub fn render<'r, 'a: 'r>(
+        &self,
+        reports: impl IntoIterator<Item = &'r JobReport<'a>>,
+        omitted: &[String],
+    ) -> rla::Result<String> {
+        let jobs = reports
+            .into_iter()
+            .map(JobReport::context)
+            .collect::<Vec<_>>();
+        Ok(self
+            .environment()
+            .template_from_str(&self.source)?
+            .render(context! { jobs, omitted })?)
+    }
+}
+
+/// Truncates the rendered `report` to `max_length` bytes if it's longer, ending it with `notice`.
+pub fn truncate(report: String, max_length: usize, notice: &str) -> String {
+    if report.len() <= max_length {
+        return report;
+    }
+    let mut end = max_length.saturating_sub(notice.len());
+    while !report.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{notice}", &report[..end])
+}
+
+/// The check run reporting the failed jobs, with the comment rendered by the template as summary
+/// and the diagnostics located in the repository as annotations.
+pub fn check_run_output(
+    reports: &[JobReport],
+    omitted: &[String],
+    summary: String,
+) -> CheckRunOutput {
+    let title = match (reports, omitted) {
+        ([report], []) => format!("The job {} failed: {}", report.name(), report.category),
+        _ => format!("{} jobs failed", reports.len() + omitted.len()),
+    };
+
+    let summary = truncate(
+        summary,
+        MAX_SUMMARY_LENGTH,
+        "\n\n*The report was truncated, see the PR comment or the logs.*",
+    );
+
+    let mut annotations = vec![];
+    for report in reports {
+        for diagnostic in &report.diagnostics {
+            let (Some(path), Some(line)) = (
+                diagnostic.file.as_deref().and_then(repository_path),
+                diagnostic.line,
+            ) else {
+                continue;
+            };
+            let annotation = CheckAnnotation {
+                path,
+                start_line: line,
+                end_line: line,
+                start_column: diagnostic.column,
+                end_column: diagnostic.column,
+                annotation_level: match diagnostic.kind {
+                    Kind::Warning => AnnotationLevel::Warning,
+                    _ => AnnotationLevel::Failure,
+                },
+                // The location is already shown by GitHub.
+                message: Diagnostic {
+                    file: None,
+                    line: None,
+                    column: None,
+                    ..diagnostic.clone()
+                }
+                .to_string(),
+                title: Some(report.name().to_string()),
+            };
+            if !annotations.contains(&annotation) {
+                annotations.push(annotation);
+            }
+        }
+    }
+
+    CheckRunOutput {
+        title,
+        summary,
+        annotations,
+    }
+}
+
+/// The path of a file relative to the root of the repository, as expected by the annotations.
+/// Absolute paths are only supported inside the `/checkout` directory, where the CI of
+/// rust-lang/rust checks out the repository.
+fn repository_path(file: &str) -> Option<String> {
+    let file = file.replace('\\', "/");
+    let path = match file.split_once("/checkout/") {
+        Some((_, path)) => path,
+        None if file.starts_with('/') || file.get(1..2) == Some(":") => return None,
+        None => &file,
+    };
+    Some(path.trim_start_matches("./").to_string())
+}
+
+/// Hidden marker at the end of the report comments, recording what they report so that they can
+/// be updated when more jobs fail on the same commit.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportMarker {
+    /// The head commit of the PR when the build ran.
+    pub head: String,
+    /// Names of the reported jobs.
+    pub jobs: Vec<String>,
+}
+
+impl ReportMarker {
+    /// Parses the marker at the end of the comment `body`, returning it along with the rest of the
+    /// body.
+    pub fn find(body: &str) -> Option<(ReportMarker, &str)> {
+        let start = body.rfind(MARKER_PREFIX)?;
+        let marker = body[start + MARKER_PREFIX.len()..]
+            .trim_end()
+            .strip_suffix(MARKER_SUFFIX)?;
+        Some((serde_json::from_str(marker).ok()?, &body[..start]))
+    }
+
+    pub fn append_to(&self, body: &str) -> String {
+        // JSON never contains `--` outside of strings, and escaping it inside them prevents job
+        // names from closing the HTML comment.
+        let marker = serde_json::to_string(self)
+            .expect("failed to serialize the marker")
+            .replace("--", "-\\u002d");
+        format!(
+            "{}\n{MARKER_PREFIX}{marker}{MARKER_SUFFIX}",
+            body.trim_end()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rla::index::Sanitized;
+    use std::fmt;
+
+    #[derive(Debug)]
+    struct Failed;
+
+    impl rla::ci::Outcome for Failed {
+        fn is_finished(&self) -> bool {
+            true
+        }
+        fn is_passed(&self) -> bool {
+            false
+        }
+        fn is_failed(&self) -> bool {
+            true
+        }
+    }
+
+    struct FakeJob(&'static str);
+
+    impl fmt::Display for FakeJob {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl rla::ci::Job for FakeJob {
+        fn id(&self) -> String {
+            self.0.to_string()
+        }
+        fn name(&self) -> &str {
+            self.0
+        }
+        fn html_url(&self) -> String {
+            format!("https://ci.example/{}", self.0)
+        }
+        fn log_url(&self) -> Option<String> {
+            Some(format!("https://ci.example/{}.log", self.0))
+        }
+        fn log_file_name(&self) -> String {
+            format!("{}.log", self.0)
+        }
+        fn outcome(&self) -> &dyn rla::ci::Outcome {
+            &Failed
+        }
+    }
+
+    fn report<'a>(job: &'a FakeJob, log: &[&str]) -> JobReport<'a> {
+        let mut log_variables = LogVariables::default();
+        for line in log {
+            log_variables.observe(&Sanitized(line));
+        }
+        JobReport {
+            job,
+            log_variables,
+            category: Category::Network,
+            signature: Some("error: failed to download".into()),
+            fingerprint: None,
+            sample: vec![],
+            recurrence: None,
+            metadata: vec![],
+            failed_tests: vec![],
+            annotations: vec![],
+            diagnostics: vec![],
+            locations: vec![],
+            blocks: vec!["error: failed to download `serde`".into()],
+        }
+    }
+
+    #[test]
+    fn test_default_template() {
+        let template = CommentTemplate::default();
+        let dist = FakeJob("dist");
+        let test = FakeJob("test <x86_64>");
+
+        let single = template
+            .render(&[report(&dist, &["[CI_JOB_NAME=dist-x86_64-linux]"])], &[])
+            .unwrap();
+        assert!(single.contains(
+            "The job **`dist-x86_64-linux`** failed! Check out the build log: \
+             [(web)](https://ci.example/dist) [(plain)](https://ci.example/dist.log)\n"
+        ));
+        assert!(single.contains("Failure category: **network error**"));
+        assert!(single.contains("```plain\nerror: failed to download `serde`\n```\n"));
+        assert!(!single.contains("Extracted from lines"));
+
+        let mut located = report(&dist, &[]);
+        located.locations = vec![None, Some("[L3-L5](https://ci.example/dist#L3)".into())];
+        let located = template.render(&[located], &[]).unwrap();
+        assert!(located
+            .contains("Extracted from lines [L3-L5](https://ci.example/dist#L3) of the log."));
+
+        let multiple = template
+            .render(
+                &[report(&dist, &[]), report(&test, &[])],
+                &["tidy".to_string()],
+            )
+            .unwrap();
+        assert!(multiple.starts_with("\n3 jobs failed!"));
+        assert!(
+            multiple.contains("<details><summary><code>test &lt;x86_64&gt;</code>: network error")
+        );
+        assert!(multiple.contains("These failed jobs were not analyzed: `tidy`."));
+    }
+
+    #[test]
+    fn test_custom_template() {
+        let template = CommentTemplate::new(
+            "{% for job in jobs %}{{ job.name }} failed ({{ job.category }}){% endfor %}".into(),
+        )
+        .unwrap();
+        let job = FakeJob("dist");
+        assert_eq!(
+            template.render(&[report(&job, &[])], &[]).unwrap(),
+            "dist failed (network error)"
+        );
+
+        assert!(CommentTemplate::new("{% if jobs %}".into()).is_err());
+    }
+
+    #[test]
+    fn test_report_marker() {
+        let marker = ReportMarker {
+            head: "3f2a9b1c0d".into(),
+            jobs: vec!["dist".into(), "test --> <!--".into()],
+        };
+        let body = marker.append_to("The job failed!\n");
+        assert!(body.starts_with("The job failed!\n<!-- rla-report {"));
+        assert_eq!(body.matches("-->").count(), 1);
+        assert_eq!(
+            ReportMarker::find(&body),
+            Some((marker, "The job failed!\n"))
+        );
+
+        assert_eq!(ReportMarker::find("The job failed!"), None);
+        assert_eq!(ReportMarker::find("<!-- rla-report {} -->"), None);
+    }
+
+    #[test]
+    fn test_check_run_output() {
+        let job = FakeJob("test");
+        let mut report = report(&job, &[]);
+        report.diagnostics = rla::diagnostics::parse(
+            &[
+                "error[E0308]: mismatched types",
+                "  --> /checkout/library/core/src/lib.rs:10:5",
+                "error: linking with `cc` failed",
+                "  --> /usr/lib/gcc/ld.rs:1:1",
+                "tidy error: ./src/tools/tidy/src/main.rs:12: trailing whitespace",
+            ]
+            .iter()
+            .map(Sanitized)
+            .collect::<Vec<_>>(),
+        );
+
+        let output = check_run_output(&[report], &[], "summary".into());
+        assert_eq!(output.title, "The job test failed: network error");
+        assert_eq!(output.summary, "summary");
+
+        let annotations = output
+            .annotations
+            .iter()
+            .map(|a| (a.path.as_str(), a.start_line, a.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            annotations,
+            vec![
+                (
+                    "library/core/src/lib.rs",
+                    10,
+                    "error[E0308]: mismatched types"
+                ),
+                (
+                    "src/tools/tidy/src/main.rs",
+                    12,
+                    "tidy error: trailing whitespace"
+                ),
+            ]
+        );
+    }
+}
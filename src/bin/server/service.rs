@@ -1,22 +1,21 @@
+use super::queue::{PushError, Queue};
 use super::QueueItem;
 
 use anyhow::bail;
 use hyper::{Body, Method, StatusCode};
 use hyper::{Request, Response};
 use std::env;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct RlaService {
     github_webhook_secret: Option<Vec<u8>>,
     reject_unverified_webhooks: bool,
-    queue: crossbeam::channel::Sender<QueueItem>,
+    queue: Arc<Queue>,
 }
 
 impl RlaService {
-    pub fn new(
-        reject_unverified_webhooks: bool,
-        queue: crossbeam::channel::Sender<QueueItem>,
-    ) -> rla::Result<RlaService> {
+    pub fn new(reject_unverified_webhooks: bool, queue: Arc<Queue>) -> rla::Result<RlaService> {
         let github_webhook_secret = match env::var("GITHUB_WEBHOOK_SECRET") {
             Err(env::VarError::NotPresent) => None,
             Err(env::VarError::NotUnicode(_)) => {
@@ -75,43 +74,15 @@ impl RlaService {
         };
 
         let item = match event {
-            "status" => {
-                let payload = match serde_json::from_slice(body) {
-                    Ok(p) => p,
+            "status" | "check_run" | "pull_request" => {
+                match QueueItem::decode(event, delivery_id, body) {
+                    Ok(item) => item,
                     Err(e) => {
-                        error!("Failed to decode 'status' web hook payload: {}", e);
+                        error!("Failed to decode '{}' web hook payload: {}", event, e);
                         return reply(StatusCode::BAD_REQUEST, "Failed to decode payload.\n");
                     }
-                };
-                QueueItem::GitHubStatus {
-                    payload,
-                    delivery_id,
                 }
             }
-            "check_run" => {
-                let payload = match serde_json::from_slice(body) {
-                    Ok(p) => p,
-                    Err(e) => {
-                        error!("Failed to decode 'check_run' web hook payload: {}", e);
-                        return reply(StatusCode::BAD_REQUEST, "Failed to decode payload.\n");
-                    }
-                };
-
-                QueueItem::GitHubCheckRun {
-                    payload,
-                    delivery_id,
-                }
-            }
-            "pull_request" => match serde_json::from_slice(body) {
-                Ok(payload) => QueueItem::GitHubPullRequest {
-                    payload,
-                    delivery_id,
-                },
-                Err(err) => {
-                    error!("Failed to decode 'pull_request' webhook payload: {}", err);
-                    return reply(StatusCode::BAD_REQUEST, "Failed to decode payload\n");
-                }
-            },
             "issue_comment" => {
                 debug!("Ignoring 'issue_comment' event.");
                 return reply(StatusCode::OK, "Event ignored.\n");
@@ -122,9 +93,23 @@ impl RlaService {
             }
         };
 
-        match self.queue.send(item) {
+        // Journaling the event syncs the file, which must not block the runtime.
+        let queue = self.queue.clone();
+        let (event, body) = (event.to_string(), body.to_vec());
+        let pushed = tokio::task::spawn_blocking(move || queue.push(&event, &body, item))
+            .await
+            .unwrap_or_else(|e| Err(PushError::Journal(e.into())));
+        match pushed {
             Ok(()) => reply(StatusCode::OK, "Event processed.\n"),
-            Err(e) => {
+            Err(PushError::Duplicate) => {
+                info!("Ignoring event delivered again.");
+                reply(StatusCode::OK, "Event already received.\n")
+            }
+            Err(PushError::Full) => {
+                error!("Rejecting event, the queue is full.");
+                reply(StatusCode::SERVICE_UNAVAILABLE, "Too many events queued.\n")
+            }
+            Err(PushError::Journal(e)) => {
                 error!("Failed to queue payload: {}", e);
                 reply(
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -0,0 +1,711 @@
+//! The destinations of the reports, selected for each build by the routes of the sinks
+//! configuration.
+
+use super::report::{self, CommentTemplate, JobReport, ReportMarker, ReportOutput};
+use anyhow::Context;
+use rla::github::BuildConclusion;
+use serde_derive::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// GitHub rejects comments longer than this.
+const MAX_COMMENT_LENGTH: usize = 65536;
+/// Ends the comments truncated to fit in `MAX_COMMENT_LENGTH`.
+const TRUNCATED_COMMENT: &str = "\n\n*The report was truncated as it's too long, see the logs.*";
+/// Name of the check runs reporting the failures.
+const CHECK_RUN_NAME: &str = "Rust Log Analyzer";
+/// Zulip rejects messages longer than this.
+const MAX_CHAT_MESSAGE_LENGTH: usize = 10000;
+const TIMEOUT_SECS: u64 = 15;
+
+/// What kind of build failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BuildKind {
+    /// A build of a PR.
+    PullRequest,
+    /// A build of a PR being merged by bors.
+    Merge,
+}
+
+/// A failure report, as sent to the sinks.
+pub struct Report<'a> {
+    /// The repository which received the build, as matched by the routes.
+    pub source_repo: &'a str,
+    pub kind: BuildKind,
+    /// The repository and the PR the failure is reported on.
+    pub repo: &'a str,
+    pub pr: u32,
+    /// The head commit of the PR when the build ran.
+    pub head: &'a str,
+    /// Whether the report is posted to a debugging issue instead of the PR.
+    pub debug: bool,
+    pub jobs: &'a [JobReport<'a>],
+    /// Names of the failed jobs which were not analyzed.
+    pub omitted: &'a [String],
+}
+
+impl Report<'_> {
+    /// The report as sent by the webhook and file sinks. The jobs have the same fields as in the
+    /// comment template.
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "repo": self.repo,
+            "pr": self.pr,
+            "head": self.head,
+            "kind": self.kind,
+            "jobs": self.jobs.iter().map(JobReport::context).collect::<Vec<_>>(),
+            "omitted": self.omitted,
+        })
+    }
+}
+
+pub trait ReportSink {
+    /// Short description of the sink, used in the logs.
+    fn describe(&self) -> String;
+    fn send(&self, report: &Report) -> rla::Result<()>;
+}
+
+/// Posts the report as a comment on the PR, or appends it to the previous report about the same
+/// commit when `update` is set. The PR is also labeled with `spurious_label`, if given, when all
+/// the failures look spurious.
+pub struct GitHubComment {
+    github: rla::github::Client,
+    template: CommentTemplate,
+    update: bool,
+    spurious_label: Option<String>,
+}
+
+impl GitHubComment {
+    pub fn new(
+        template: CommentTemplate,
+        update: bool,
+        spurious_label: Option<String>,
+    ) -> rla::Result<Self> {
+        Ok(GitHubComment {
+            github: rla::github::Client::new()?,
+            template,
+            update,
+            spurious_label,
+        })
+    }
+
+    /// Labels the PR if all the reported failures look spurious. Failing to do so doesn't fail
+    /// the report, as the comment was already posted.
+    fn label_spurious(&self, report: &Report) {
+        let Some(ref label) = self.spurious_label else {
+            return;
+        };
+        if report.jobs.is_empty() || !report.jobs.iter().all(|r| r.category.is_spurious()) {
+            return;
+        }
+
+        if report.debug {
+            warn!(
+                "Would add the `{}` label to '{}#{}'",
+                label, report.repo, report.pr
+            );
+        } else if let Err(e) = self.github.add_label(report.repo, report.pr, label) {
+            warn!("failed to add the `{label}` label: {e}");
+        }
+    }
+
+    /// Appends the reports of the jobs which weren't reported yet to the previous report about the
+    /// same commit, or posts a new report if there's none.
+    fn update(&self, report: &Report) -> rla::Result<()> {
+        let comments = self.github.query_own_comments(report.repo, report.pr)?;
+        let previous = comments
+            .iter()
+            .rev()
+            .filter(|comment| !comment.is_minimized)
+            .find_map(|comment| {
+                let (marker, body) = ReportMarker::find(&comment.body)?;
+                (marker.head == report.head).then_some((comment, marker, body))
+            });
+
+        let Some((comment, mut marker, body)) = previous else {
+            let marker = ReportMarker {
+                head: report.head.to_string(),
+                jobs: report
+                    .jobs
+                    .iter()
+                    .map(|r| r.job.name().to_string())
+                    .collect(),
+            };
+            let comment = self.template.render(report.jobs, report.omitted)?;
+            return self.github.post_comment(
+                report.repo,
+                report.pr,
+                &fit_comment(comment, Some(&marker)),
+            );
+        };
+
+        let new = report
+            .jobs
+            .iter()
+            .filter(|r| !marker.jobs.iter().any(|job| job == r.job.name()))
+            .collect::<Vec<_>>();
+        if new.is_empty() {
+            info!("all the failed jobs were already reported, not updating the report");
+            return Ok(());
+        }
+        marker
+            .jobs
+            .extend(new.iter().map(|r| r.job.name().to_string()));
+
+        let appended = self.template.render(new, report.omitted)?;
+        let updated = marker.append_to(&format!("{}\n\n---\n{}", body.trim_end(), appended));
+        if updated.len() > MAX_COMMENT_LENGTH {
+            info!("the updated report would be too long, posting a new one");
+            self.github.post_comment(
+                report.repo,
+                report.pr,
+                &fit_comment(appended, Some(&marker)),
+            )
+        } else {
+            info!("updating the previous report {}", comment.id);
+            self.github.edit_comment(&comment.id, &updated)
+        }
+    }
+}
+
+impl ReportSink for GitHubComment {
+    fn describe(&self) -> String {
+        "GitHub comment".into()
+    }
+
+    fn send(&self, report: &Report) -> rla::Result<()> {
+        if self.update {
+            self.update(report)?;
+        } else {
+            let comment = self.template.render(report.jobs, report.omitted)?;
+            self.github
+                .post_comment(report.repo, report.pr, &fit_comment(comment, None))?;
+        }
+        self.label_spurious(report);
+        Ok(())
+    }
+}
+
+/// Truncates the `comment` so that it fits in a GitHub comment along with the `marker`, as the
+/// budget of the extracted lines doesn't account for the rest of the template.
+fn fit_comment(comment: String, marker: Option<&ReportMarker>) -> String {
+    match marker {
+        Some(marker) => {
+            let max_length = MAX_COMMENT_LENGTH - marker.append_to("").len();
+            marker.append_to(&report::truncate(comment, max_length, TRUNCATED_COMMENT))
+        }
+        None => report::truncate(comment, MAX_COMMENT_LENGTH, TRUNCATED_COMMENT),
+    }
+}
+
+/// Creates a check run on the head commit of the PR. This requires a GitHub App token.
+pub struct GitHubCheckRun {
+    github: rla::github::Client,
+    template: CommentTemplate,
+}
+
+impl GitHubCheckRun {
+    pub fn new(template: CommentTemplate) -> rla::Result<Self> {
+        Ok(GitHubCheckRun {
+            github: rla::github::Client::new()?,
+            template,
+        })
+    }
+}
+
+impl ReportSink for GitHubCheckRun {
+    fn describe(&self) -> String {
+        "GitHub check run".into()
+    }
+
+    fn send(&self, report: &Report) -> rla::Result<()> {
+        if report.debug {
+            warn!(
+                "Would create a check run on '{}@{}'",
+                report.repo, report.head
+            );
+            return Ok(());
+        }
+
+        let summary = self.template.render(report.jobs, report.omitted)?;
+        self.github.create_check_run(
+            report.repo,
+            report.head,
+            CHECK_RUN_NAME,
+            BuildConclusion::Neutral,
+            &report::check_run_output(report.jobs, report.omitted, summary),
+        )
+    }
+}
+
+/// POSTs the report as JSON to a URL.
+pub struct Webhook {
+    client: reqwest::blocking::Client,
+    url: String,
+}
+
+impl Webhook {
+    pub fn new(url: String) -> rla::Result<Self> {
+        Ok(Webhook {
+            client: reqwest::blocking::Client::builder()
+                .user_agent(rla::USER_AGENT)
+                .timeout(Duration::from_secs(TIMEOUT_SECS))
+                .build()?,
+            url,
+        })
+    }
+}
+
+impl ReportSink for Webhook {
+    fn describe(&self) -> String {
+        format!("webhook {}", self.url)
+    }
+
+    fn send(&self, report: &Report) -> rla::Result<()> {
+        self.client
+            .post(&self.url)
+            .json(&report.to_json())
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Sends a short summary of the report to a Zulip stream. The API key of the bot is read from the
+/// `ZULIP_API_KEY` environment variable.
+pub struct Zulip {
+    client: reqwest::blocking::Client,
+    /// The URL of the Zulip server, e.g. `https://rust-lang.zulipchat.com`.
+    site: String,
+    bot_email: String,
+    api_key: String,
+    stream: String,
+    topic: String,
+}
+
+impl Zulip {
+    pub fn new(
+        site: String,
+        bot_email: String,
+        stream: String,
+        topic: String,
+    ) -> rla::Result<Self> {
+        Ok(Zulip {
+            client: reqwest::blocking::Client::builder()
+                .user_agent(rla::USER_AGENT)
+                .timeout(Duration::from_secs(TIMEOUT_SECS))
+                .build()?,
+            site: site.trim_end_matches('/').to_string(),
+            bot_email,
+            api_key: std::env::var("ZULIP_API_KEY")
+                .with_context(|| "failed to read ZULIP_API_KEY env var")?,
+            stream,
+            topic,
+        })
+    }
+
+    fn message(report: &Report) -> String {
+        let mut message = format!(
+            "[{repo}#{pr}](https://github.com/{repo}/pull/{pr}): {count} failed on `{head}`",
+            repo = report.repo,
+            pr = report.pr,
+            count = match report.jobs.len() + report.omitted.len() {
+                1 => "a job".to_string(),
+                n => format!("{n} jobs"),
+            },
+            head = &report.head[..report.head.len().min(10)],
+        );
+        for job in report.jobs {
+            message.push_str(&format!(
+                "\n- `{}`: {} ([log]({}))",
+                job.name().replace('`', ""),
+                job.category,
+                job.job.html_url()
+            ));
+            if let Some(diagnostic) = job.diagnostics.first() {
+                message.push_str(&format!(
+                    "\n  ```text\n  {}\n  ```",
+                    diagnostic.to_string().replace('\n', "\n  ")
+                ));
+            }
+        }
+        for name in report.omitted {
+            message.push_str(&format!("\n- `{}`: not analyzed", name.replace('`', "")));
+        }
+
+        if message.len() > MAX_CHAT_MESSAGE_LENGTH {
+            let mut end = MAX_CHAT_MESSAGE_LENGTH - 3;
+            while !message.is_char_boundary(end) {
+                end -= 1;
+            }
+            message.truncate(end);
+            message.push_str("...");
+        }
+        message
+    }
+}
+
+impl ReportSink for Zulip {
+    fn describe(&self) -> String {
+        format!("Zulip stream {}", self.stream)
+    }
+
+    fn send(&self, report: &Report) -> rla::Result<()> {
+        self.client
+            .post(format!("{}/api/v1/messages", self.site))
+            .basic_auth(&self.bot_email, Some(&self.api_key))
+            .form(&[
+                ("type", "stream"),
+                ("to", self.stream.as_str()),
+                ("topic", self.topic.as_str()),
+                ("content", Zulip::message(report).as_str()),
+            ])
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Appends the report as a line of JSON to a local file.
+pub struct File {
+    path: PathBuf,
+}
+
+impl ReportSink for File {
+    fn describe(&self) -> String {
+        format!("file {}", self.path.display())
+    }
+
+    fn send(&self, report: &Report) -> rla::Result<()> {
+        append_json_line(&self.path, report.to_json())
+    }
+}
+
+/// Stores the reports, along with the comment they would be posted as, instead of sending them.
+/// Used by `--dry-run` to test a new index or configuration against live traffic.
+///
+/// The reports are appended to `path` if it's a `.jsonl` file, or otherwise written to one
+/// Markdown and one JSON file per report in the `path` directory.
+pub struct DryRun {
+    path: PathBuf,
+    template: CommentTemplate,
+}
+
+impl ReportSink for DryRun {
+    fn describe(&self) -> String {
+        format!("dry run {}", self.path.display())
+    }
+
+    fn send(&self, report: &Report) -> rla::Result<()> {
+        let comment = self.template.render(report.jobs, report.omitted)?;
+        info!("dry run, would have reported:\n{}", comment);
+
+        let mut json = report.to_json();
+        json["comment"] = comment.as_str().into();
+        if self.path.extension().is_some_and(|ext| ext == "jsonl") {
+            return append_json_line(&self.path, json);
+        }
+
+        std::fs::create_dir_all(&self.path)?;
+        let name = format!(
+            "{}-{}-{}-{}",
+            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            report.repo.replace('/', "_"),
+            report.pr,
+            report.head
+        );
+        std::fs::write(self.path.join(format!("{name}.md")), &comment)?;
+        std::fs::write(
+            self.path.join(format!("{name}.json")),
+            serde_json::to_vec_pretty(&json)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Appends the `json` object to the file at `path`, with the current time as `timestamp`.
+fn append_json_line(path: &Path, mut json: serde_json::Value) -> rla::Result<()> {
+    json["timestamp"] = SystemTime::now()
+        .duration_since(UNIX_EPOCH)?
+        .as_secs()
+        .into();
+
+    let mut line = serde_json::to_vec(&json)?;
+    line.push(b'\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(&line)?;
+    Ok(())
+}
+
+/// A sink, as defined in the sinks configuration.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum SinkConfig {
+    GithubComment {
+        #[serde(default)]
+        update: bool,
+        /// Label to add to the PR when all the failures look spurious.
+        spurious_label: Option<String>,
+    },
+    GithubCheckRun,
+    Webhook {
+        url: String,
+    },
+    Zulip {
+        site: String,
+        bot_email: String,
+        stream: String,
+        topic: String,
+    },
+    File {
+        path: PathBuf,
+    },
+}
+
+/// Which builds are reported to a sink.
+#[derive(Deserialize)]
+struct RouteConfig {
+    /// Only the builds received by this repository, if given.
+    repo: Option<String>,
+    /// Only the builds of this kind, if given.
+    builds: Option<BuildKind>,
+    sink: SinkConfig,
+}
+
+struct Route {
+    repo: Option<String>,
+    builds: Option<BuildKind>,
+    sink: Box<dyn ReportSink + Send + Sync>,
+}
+
+impl Route {
+    fn matches(&self, report: &Report) -> bool {
+        self.repo
+            .as_deref()
+            .is_none_or(|repo| repo == report.source_repo)
+            && self.builds.is_none_or(|kind| kind == report.kind)
+    }
+}
+
+/// The sinks the reports are sent to.
+pub struct Sinks {
+    routes: Vec<Route>,
+}
+
+impl Sinks {
+    /// Stores all the reports locally instead of sending them, see [`DryRun`].
+    pub fn dry_run(path: PathBuf, template: &CommentTemplate) -> Self {
+        Sinks {
+            routes: vec![Route {
+                repo: None,
+                builds: None,
+                sink: Box::new(DryRun {
+                    path,
+                    template: template.clone(),
+                }),
+            }],
+        }
+    }
+
+    /// Reports all the builds to GitHub, as selected by the `--output`, `--update-comments` and
+    /// `--spurious-label` flags.
+    pub fn from_output(
+        output: ReportOutput,
+        update_comments: bool,
+        spurious_label: Option<String>,
+        template: &CommentTemplate,
+    ) -> rla::Result<Self> {
+        let mut sinks: Vec<Box<dyn ReportSink + Send + Sync>> = vec![];
+        if output.creates_check_run() {
+            sinks.push(Box::new(GitHubCheckRun::new(template.clone())?));
+        }
+        if output.posts_comment() {
+            sinks.push(Box::new(GitHubComment::new(
+                template.clone(),
+                update_comments,
+                spurious_label,
+            )?));
+        }
+        Ok(Sinks {
+            routes: sinks
+                .into_iter()
+                .map(|sink| Route {
+                    repo: None,
+                    builds: None,
+                    sink,
+                })
+                .collect(),
+        })
+    }
+
+    /// Loads the routes from a JSON file, containing a list of objects with the `sink` to send
+    /// the reports to, and optionally the `repo` and the kind of `builds` (`pull-request` or
+    /// `merge`) to send.
+    pub fn load(path: &Path, template: &CommentTemplate) -> rla::Result<Self> {
+        let config: Vec<RouteConfig> = serde_json::from_slice(&std::fs::read(path)?)?;
+        let routes = config
+            .into_iter()
+            .map(|route| {
+                let sink: Box<dyn ReportSink + Send + Sync> = match route.sink {
+                    SinkConfig::GithubComment {
+                        update,
+                        spurious_label,
+                    } => Box::new(GitHubComment::new(
+                        template.clone(),
+                        update,
+                        spurious_label,
+                    )?),
+                    SinkConfig::GithubCheckRun => Box::new(GitHubCheckRun::new(template.clone())?),
+                    SinkConfig::Webhook { url } => Box::new(Webhook::new(url)?),
+                    SinkConfig::Zulip {
+                        site,
+                        bot_email,
+                        stream,
+                        topic,
+                    } => Box::new(Zulip::new(site, bot_email, stream, topic)?),
+                    SinkConfig::File { path } => Box::new(File { path }),
+                };
+                Ok(Route {
+                    repo: route.repo,
+                    builds: route.builds,
+                    sink,
+                })
+            })
+            .collect::<rla::Result<_>>()?;
+        Ok(Sinks { routes })
+    }
+
+    /// Sends the report to all the matching sinks. Fails only if no sink received it.
+    pub fn send(&self, report: &Report) -> rla::Result<()> {
+        let mut sent = false;
+        let mut error = None;
+        for route in self.routes.iter().filter(|route| route.matches(report)) {
+            match route.sink.send(report) {
+                Ok(()) => sent = true,
+                Err(e) => {
+                    warn!(
+                        "failed to send the report to {}: {}",
+                        route.sink.describe(),
+                        e
+                    );
+                    error = Some(e);
+                }
+            }
+        }
+
+        match error {
+            Some(e) if !sent => Err(e),
+            _ if !sent => {
+                info!("no sink is configured for this build, not reporting");
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_comment() {
+        assert_eq!("short", fit_comment("short".into(), None));
+
+        let marker = ReportMarker {
+            head: "abc".into(),
+            jobs: vec!["job".into()],
+        };
+        let comment = fit_comment("é".repeat(MAX_COMMENT_LENGTH), Some(&marker));
+        assert!(comment.len() <= MAX_COMMENT_LENGTH);
+        let (found, body) = ReportMarker::find(&comment).unwrap();
+        assert_eq!("abc", found.head);
+        assert!(body.trim_end().ends_with(TRUNCATED_COMMENT.trim()));
+    }
+
+    #[test]
+    fn test_routes() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let (merges, prs) = (dir.join("merges.jsonl"), dir.join("prs.jsonl"));
+        let config = dir.join("sinks.json");
+        std::fs::write(
+            &config,
+            serde_json::to_vec(&serde_json::json!([
+                {"repo": "rust-lang/rust", "builds": "merge", "sink": {"type": "file", "path": merges}},
+                {"builds": "pull-request", "sink": {"type": "file", "path": prs}},
+            ]))
+            .unwrap(),
+        )
+        .unwrap();
+
+        let sinks = Sinks::load(&config, &CommentTemplate::default()).unwrap();
+        let omitted = vec!["dist".to_string()];
+        let mut report = Report {
+            source_repo: "rust-lang/rust",
+            kind: BuildKind::PullRequest,
+            repo: "rust-lang/rust",
+            pr: 42,
+            head: "3f2a9b1c0d",
+            debug: false,
+            jobs: &[],
+            omitted: &omitted,
+        };
+        sinks.send(&report).unwrap();
+        report.kind = BuildKind::Merge;
+        report.source_repo = "rust-lang/cargo";
+        sinks.send(&report).unwrap();
+
+        assert!(!merges.exists());
+        let lines = std::fs::read_to_string(&prs).unwrap();
+        let json: serde_json::Value = serde_json::from_str(lines.trim()).unwrap();
+        assert_eq!(json["pr"], 42);
+        assert_eq!(json["kind"], "pull-request");
+        assert_eq!(json["omitted"][0], "dist");
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let report = Report {
+            source_repo: "rust-lang/rust",
+            kind: BuildKind::PullRequest,
+            repo: "rust-lang/rust",
+            pr: 42,
+            head: "3f2a9b1c0d",
+            debug: false,
+            jobs: &[],
+            omitted: &["dist".to_string()],
+        };
+
+        Sinks::dry_run(dir.join("reports"), &CommentTemplate::default())
+            .send(&report)
+            .unwrap();
+        let mut files = std::fs::read_dir(dir.join("reports"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(files.len(), 2);
+        assert!(files[0]
+            .to_str()
+            .unwrap()
+            .ends_with("-rust-lang_rust-42-3f2a9b1c0d.json"));
+        let comment = std::fs::read_to_string(&files[1]).unwrap();
+        assert!(comment.contains("These failed jobs were not analyzed: `dist`."));
+
+        Sinks::dry_run(dir.join("reports.jsonl"), &CommentTemplate::default())
+            .send(&report)
+            .unwrap();
+        let line = std::fs::read_to_string(dir.join("reports.jsonl")).unwrap();
+        let json: serde_json::Value = serde_json::from_str(line.trim()).unwrap();
+        assert_eq!(json["comment"].as_str(), Some(comment.as_str()));
+    }
+}
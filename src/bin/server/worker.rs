@@ -1,43 +1,91 @@
-use super::QueueItem;
+use super::report::{JobReport, Recurrence};
+use super::sinks::{BuildKind, Report, Sinks};
+use super::{Queue, QueueItem};
 
 use crate::rla::ci::{self, BuildCommit, CiPlatform};
 use anyhow::bail;
-use rla::index::IndexStorage;
-use std::collections::{HashSet, VecDeque};
+use rla::classify::Category;
+use rla::diagnostics::Kind;
+use rla::fingerprint::{self, Fingerprint, FingerprintStore, Occurrence};
+use rla::index::{IndexStorage, Sanitized};
+use rla::workflow_commands::Level;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::hash::Hash;
+use std::io;
+use std::path::PathBuf;
 use std::str;
-use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 const MINIMUM_DELAY_BETWEEN_INDEX_BACKUPS: Duration = Duration::from_secs(60 * 60);
 const SILENCE_LABEL: &str = "rla-silenced";
+/// How many recognized diagnostics are listed in the comments.
+const MAX_DIAGNOSTICS: usize = 5;
+/// How many workflow error annotations are listed in the comments.
+const MAX_ANNOTATIONS: usize = 5;
+/// How many failed tests are listed in the comments.
+const MAX_FAILED_TESTS: usize = 20;
 
 pub struct Worker {
     debug_post: Option<(String, u32)>,
     index_file: IndexStorage,
-    index: rla::Index,
+    /// Snapshot of the index the failures are extracted with, replaced by an updated copy after
+    /// learning from a build so that the extractions never wait for the learning.
+    index: RwLock<Arc<rla::Index>>,
+    /// Held while updating the index, so that the lines learned by other workers aren't lost.
+    updating_index: Mutex<()>,
     extract_config: rla::extract::Config,
+    line_output: rla::extract::LineOutput,
+    budget: rla::budget::Budget,
+    classifier: rla::classify::Classifier,
     github: rla::github::Client,
-    queue: crossbeam::channel::Receiver<QueueItem>,
-    ci: Box<dyn CiPlatform + Send>,
+    queue: Arc<Queue>,
+    ci: Box<dyn CiPlatform + Send + Sync>,
     repo: String,
     secondary_repos: Vec<String>,
     query_builds_from_primary_repo: bool,
-
-    recently_notified: RecentlySeen<u64>,
-    recently_learned: RecentlySeen<String>,
-
-    last_index_backup: Option<Instant>,
+    baseline_branch: Option<String>,
+    baselines: Mutex<BaselineCache>,
+    fingerprints: Option<Mutex<FingerprintStore>>,
+    /// Log variables shown in the reports, when defined by the log.
+    report_variables: Vec<String>,
+    /// Maximum number of failed jobs analyzed for a single build.
+    max_reported_jobs: usize,
+    sinks: Sinks,
+    /// Whether to avoid writing anything to GitHub, the reports being stored by the sinks. The
+    /// index and the fingerprints aren't saved either, as they might be the production ones.
+    dry_run: bool,
+
+    recently_notified: Mutex<RecentlySeen<u64>>,
+    recently_learned: Mutex<RecentlySeen<String>>,
+    reporting: InProgress<u64>,
+    /// Held while sending a report, as the GitHub comment sink can update the previous report on
+    /// the PR, which must not race with another worker reporting a build of the same PR.
+    sending: Mutex<()>,
+    learning: InProgress<String>,
+
+    last_index_backup: Mutex<Option<Instant>>,
 }
 
 impl Worker {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         index_file: IndexStorage,
         debug_post: Option<String>,
-        queue: crossbeam::channel::Receiver<QueueItem>,
-        ci: Box<dyn CiPlatform + Send>,
+        line_output: rla::extract::LineOutput,
+        garbage: rla::garbage::GarbageFilter,
+        classifier: rla::classify::Classifier,
+        queue: Arc<Queue>,
+        ci: Box<dyn CiPlatform + Send + Sync>,
         repo: String,
         secondary_repos: Vec<String>,
         query_builds_from_primary_repo: bool,
+        baseline_branch: Option<String>,
+        fingerprint_store: Option<PathBuf>,
+        report_variables: Vec<String>,
+        max_reported_jobs: usize,
+        sinks: Sinks,
+        dry_run: bool,
     ) -> rla::Result<Worker> {
         let debug_post = match debug_post {
             None => None,
@@ -54,26 +102,49 @@ impl Worker {
 
         Ok(Worker {
             debug_post,
-            index: rla::Index::load(&index_file)?,
+            index: RwLock::new(Arc::new(rla::Index::load(&index_file)?)),
+            updating_index: Mutex::new(()),
             index_file,
-            extract_config: Default::default(),
+            extract_config: rla::extract::Config {
+                garbage,
+                ..Default::default()
+            },
+            line_output,
+            budget: Default::default(),
+            classifier,
             github: rla::github::Client::new()?,
             queue,
             ci,
             repo,
             secondary_repos,
             query_builds_from_primary_repo,
-
-            recently_notified: RecentlySeen::new(32),
-            recently_learned: RecentlySeen::new(256),
-
-            last_index_backup: None,
+            baseline_branch,
+            baselines: Default::default(),
+            fingerprints: fingerprint_store
+                .map(|path| FingerprintStore::load(&path, fingerprint::DEFAULT_MAX_AGE.as_secs()))
+                .transpose()?
+                .map(Mutex::new),
+            report_variables,
+            max_reported_jobs,
+            sinks,
+            dry_run,
+
+            recently_notified: Mutex::new(RecentlySeen::new(32)),
+            recently_learned: Mutex::new(RecentlySeen::new(256)),
+            reporting: InProgress::new(),
+            sending: Mutex::new(()),
+            learning: InProgress::new(),
+
+            last_index_backup: Mutex::new(None),
         })
     }
 
-    pub fn main(&mut self) -> rla::Result<()> {
+    /// Processes the queued events until asked to shut down. Can be called from multiple threads
+    /// sharing the worker.
+    pub fn main(&self) -> rla::Result<()> {
         loop {
             let item = self.queue.recv()?;
+            let delivery_id = item.delivery_id().map(str::to_string);
 
             let span = span!(
                 tracing::Level::INFO,
@@ -88,6 +159,10 @@ impl Worker {
                 Ok(ProcessOutcome::Exit) => return Ok(()),
                 Err(e) => error!("Processing queue item failed: {}", e),
             }
+            // Failed items are not retried either, as they would likely fail again.
+            if let Some(id) = delivery_id {
+                self.queue.complete(&id);
+            }
         }
     }
 
@@ -98,10 +173,10 @@ impl Worker {
         self.secondary_repos.iter().find(|r| *r == repo).is_some()
     }
 
-    fn process(&mut self, item: QueueItem, span: &tracing::Span) -> rla::Result<ProcessOutcome> {
+    fn process(&self, item: QueueItem, span: &tracing::Span) -> rla::Result<ProcessOutcome> {
         let (repo, build_id, outcome) = match &item {
             QueueItem::GitHubStatus { payload, .. } => {
-                match self.ci.build_id_from_github_status(&payload) {
+                match self.ci.build_id_from_github_status(payload) {
                     Some(id) if self.is_repo_valid(&payload.repository.full_name) => {
                         (&payload.repository.full_name, id, None)
                     }
@@ -115,7 +190,7 @@ impl Worker {
                 }
             }
             QueueItem::GitHubCheckRun { payload, .. } => {
-                match self.ci.build_id_from_github_check(&payload) {
+                match self.ci.build_id_from_github_check(payload) {
                     Some(id) if self.is_repo_valid(&payload.repository.full_name) => (
                         &payload.repository.full_name,
                         id,
@@ -136,13 +211,13 @@ impl Worker {
             }
 
             QueueItem::GracefulShutdown => {
-                info!("persisting the index to disk before shutting down");
-                self.index.save(&self.index_file)?;
+                // Pass the request on to the other workers.
+                self.queue.shutdown();
                 return Ok(ProcessOutcome::Exit);
             }
         };
 
-        span.record("build_id", &build_id);
+        span.record("build_id", build_id);
 
         info!("started processing");
 
@@ -154,7 +229,7 @@ impl Worker {
         let build = self.ci.query_build(query_from, build_id)?;
 
         let outcome = match outcome {
-            Some(outcome) if self.ci.is_build_outcome_unreliable() => &*outcome,
+            Some(outcome) if self.ci.is_build_outcome_unreliable() => outcome,
             _ => build.outcome(),
         };
 
@@ -169,7 +244,7 @@ impl Worker {
 
         // Avoid processing the same build multiple times.
         if !outcome.is_passed() {
-            self.report_failed(build_id, build.as_ref())?;
+            self.report_failed(repo, build_id, build.as_ref())?;
         }
         if build.pr_number().is_some() || build.branch_name() == "automation/bors/auto" {
             info!("learning from the log");
@@ -181,43 +256,77 @@ impl Worker {
         Ok(ProcessOutcome::Continue)
     }
 
-    fn report_failed(&mut self, build_id: u64, build: &dyn rla::ci::Build) -> rla::Result<()> {
-        if self.recently_notified.recently_witnessed(&build_id) {
+    fn report_failed(
+        &self,
+        source_repo: &str,
+        build_id: u64,
+        build: &dyn rla::ci::Build,
+    ) -> rla::Result<()> {
+        // GitHub sends an event for each check run of the build, which can be processed by
+        // several workers at the same time.
+        let Some(_reporting) = self.reporting.claim(build_id) else {
+            info!("avoided reporting build already being reported");
+            return Ok(());
+        };
+        if self
+            .recently_notified
+            .lock()
+            .unwrap()
+            .recently_witnessed(&build_id)
+        {
             info!("avoided reporting recently notified build");
             return Ok(());
         }
 
         info!("preparing report");
 
-        let job = match build.jobs().iter().find(|j| j.outcome().is_failed()) {
-            Some(job) => *job,
-            None => bail!("No failed job found, cannot report."),
-        };
-
-        let log = match ci::download_log(self.ci.as_ref(), job, self.github.internal()) {
-            Some(res) => res?,
-            None => bail!("No log for failed job"),
-        };
-
-        let lines = rla::sanitize::split_lines(&log)
+        let jobs = build.jobs();
+        let failed = jobs
             .iter()
-            .map(|l| rla::index::Sanitized(rla::sanitize::clean(self.ci.as_ref(), l)))
+            .filter(|j| j.outcome().is_failed())
+            .copied()
             .collect::<Vec<_>>();
-
-        let blocks = rla::extract::extract(&self.extract_config, &self.index, &lines);
-
-        let blocks = blocks
+        if failed.is_empty() {
+            bail!("No failed job found, cannot report.");
+        }
+        let (analyzed, omitted) = failed.split_at(failed.len().min(self.max_reported_jobs));
+        let mut omitted = omitted
             .iter()
-            .map(|block| {
-                block
-                    .iter()
-                    .map(|line| String::from_utf8_lossy(&line.0).into_owned())
-                    .collect::<Vec<_>>()
-                    .join("\n")
-            })
+            .map(|job| job.name().to_string())
             .collect::<Vec<_>>();
 
-        let extracted = blocks.join("\n---\n");
+        // Lines also present in the last passed build of the baseline branch are not the cause of
+        // the failure. Looking it up costs a few API calls, so it's shared by the reported jobs.
+        let baseline_build = match self.baseline_branch {
+            Some(ref branch) => self
+                .ci
+                .query_latest_passed_build(&self.repo, branch, build)
+                .unwrap_or_else(|e| {
+                    warn!("failed to query the baseline build, ignoring it: {}", e);
+                    None
+                }),
+            None => None,
+        };
+
+        // The size limits of the comment are shared by the reported jobs.
+        let budget = self.budget.split(analyzed.len());
+        let mut reports = vec![];
+        for &job in analyzed {
+            match self.analyze_job(job, &budget, baseline_build.as_deref()) {
+                Ok(report) => reports.push(report),
+                Err(e) if analyzed.len() > 1 => {
+                    warn!(
+                        "failed to analyze {}, leaving it out of the report: {}",
+                        job, e
+                    );
+                    omitted.push(job.name().to_string());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if reports.is_empty() {
+            bail!("None of the failed jobs could be analyzed, cannot report.");
+        }
 
         // Some CI providers return a merge commit instead of the head commit of the branch/PR when
         // querying the build. If the provider returned a merge commit, this fetches the related
@@ -241,15 +350,12 @@ impl Worker {
             .commit
             .message;
 
-        let log_variables = rla::log_variables::LogVariables::extract(&lines);
-
         let (pr, is_bors) = if let Some(pr) = build.pr_number() {
             (pr, false)
         } else {
             static BORS_MERGE_PREFIX: &str = "Auto merge of #";
 
-            if commit_message.starts_with(BORS_MERGE_PREFIX) {
-                let s = &commit_message[BORS_MERGE_PREFIX.len()..];
+            if let Some(s) = commit_message.strip_prefix(BORS_MERGE_PREFIX) {
                 (
                     s[..s.find(' ').ok_or_else(|| {
                         anyhow::format_err!("Invalid bors commit message: '{}'", commit_message)
@@ -257,7 +363,10 @@ impl Worker {
                         .parse()?,
                     true,
                 )
-            } else if let Some(number) = log_variables.pr_number {
+            } else if let Some(number) = reports
+                .iter()
+                .find_map(|report| report.log_variables.pr_number())
+            {
                 (number.parse()?, false)
             } else {
                 bail!("Could not determine PR number, cannot report.");
@@ -280,7 +389,31 @@ impl Worker {
             }
         }
 
-        let (repo, pr) = match self.debug_post {
+        if let Some(ref store) = self.fingerprints {
+            let store = store.lock().unwrap();
+            for report in &mut reports {
+                let Some(ref fingerprint) = report.fingerprint else {
+                    continue;
+                };
+
+                let seen = store
+                    .occurrences(fingerprint)
+                    .iter()
+                    .filter(|o| o.repo != self.repo || o.pr != pr)
+                    .collect::<Vec<_>>();
+                report.recurrence = seen.last().map(|last| Recurrence {
+                    count: seen.len(),
+                    days: fingerprint::DEFAULT_MAX_AGE.as_secs() / (24 * 60 * 60),
+                    reference: if last.repo == self.repo {
+                        format!("#{}", last.pr)
+                    } else {
+                        format!("{}#{}", last.repo, last.pr)
+                    },
+                });
+            }
+        }
+
+        let (post_repo, post_pr) = match self.debug_post {
             Some((ref repo, pr_override)) => {
                 warn!(
                     "Would post to '{}#{}', debug override to '{}#{}'",
@@ -291,49 +424,284 @@ impl Worker {
             None => (self.repo.as_str(), pr),
         };
 
-        let opening = match log_variables.job_name {
-            Some(job_name) => format!("The job **`{}`**", job_name),
-            None => "A job".to_owned(),
+        let _sending = self.sending.lock().unwrap();
+        self.sinks.send(&Report {
+            source_repo,
+            kind: if is_bors {
+                BuildKind::Merge
+            } else {
+                BuildKind::PullRequest
+            },
+            repo: post_repo,
+            pr: post_pr,
+            head: &commit_sha,
+            debug: self.debug_post.is_some(),
+            jobs: &reports,
+            omitted: &omitted,
+        })?;
+
+        // Only the failures which were reported are remembered, so that the retries of a report
+        // which failed to be sent don't count as recurrences.
+        if let Some(ref store) = self.fingerprints {
+            let mut store = store.lock().unwrap();
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            for report in &reports {
+                let Some(ref fingerprint) = report.fingerprint else {
+                    continue;
+                };
+                store.record(
+                    fingerprint.clone(),
+                    &report.sample,
+                    Occurrence {
+                        repo: self.repo.clone(),
+                        pr,
+                        timestamp,
+                    },
+                );
+            }
+            if self.dry_run {
+                debug!("dry run, not saving the fingerprints");
+            } else if let Err(e) = store.save() {
+                warn!("failed to save the fingerprints: {}", e);
+            }
+        }
+
+        info!("marked build {} as recently notified", build_id);
+        self.recently_notified.lock().unwrap().store(build_id);
+
+        Ok(())
+    }
+
+    /// Extracts the likely cause of the failure from the log of a failed `job`, rendering the
+    /// extracted lines within the `budget`.
+    fn analyze_job<'a>(
+        &self,
+        job: &'a dyn rla::ci::Job,
+        budget: &rla::budget::Budget,
+        baseline_build: Option<&dyn rla::ci::Build>,
+    ) -> rla::Result<JobReport<'a>> {
+        let log = match ci::open_log(self.ci.as_ref(), job, self.github.internal()) {
+            Some(res) => io::BufReader::new(res?),
+            None => bail!("No log for failed job"),
         };
-        let trailer = match log_variables.doc_url {
-            Some(url) => format!(
-                r#"
-> [!IMPORTANT]
-> For more information how to resolve CI failures of this job, visit this [link]({url})."#
-            ),
-            None => "".to_string(),
+
+        let baseline = match baseline_build {
+            Some(build) => self.load_baseline(build, job).unwrap_or_else(|e| {
+                warn!("failed to load the baseline log, ignoring it: {}", e);
+                None
+            }),
+            None => None,
         };
-        let plain_enhanced = match job.log_enhanced_url() {
-            Some(enhanced_url) => format!(" [(plain enhanced)]({enhanced_url})"),
-            None => "".to_string(),
+
+        // The log is processed while it's being downloaded, only keeping the extracted blocks in
+        // memory, as some logs are hundreds of megabytes long.
+        let mut log_variables =
+            rla::log_variables::LogVariables::with_wanted(&self.report_variables);
+        let mut failed_tests = rla::test_summary::FailedTests::default();
+        let mut workflow_commands = rla::workflow_commands::WorkflowCommands::default();
+        let mut read_error = None;
+        let lines = rla::sanitize::read_lines(self.ci.as_ref(), log)
+            .map_while(|line| line.map_err(|e| read_error = Some(e)).ok())
+            .inspect(|line| {
+                log_variables.observe(line);
+                failed_tests.observe(line);
+                workflow_commands.observe(line);
+            });
+
+        let index = self.index();
+        let mut extractor = rla::extract::StreamingExtractor::new(&self.extract_config, &index);
+        if let Some(ref baseline) = baseline {
+            extractor = extractor.with_baseline(baseline);
+        }
+        let blocks = extractor.blocks(lines).ranked();
+        if let Some(e) = read_error {
+            return Err(e.into());
+        }
+
+        let matched = self
+            .classifier
+            .find_match(blocks.iter().flat_map(|block| &block.lines));
+        let category = matched
+            .as_ref()
+            .map_or(Category::Unknown, |m| m.signature.category);
+        info!("{} classified as: {}", job, category);
+
+        let annotations = workflow_commands
+            .annotations()
+            .iter()
+            .filter(|a| a.level == Level::Error && !a.is_generic())
+            .take(MAX_ANNOTATIONS)
+            .map(|a| a.to_string())
+            .collect();
+
+        // Warnings rarely fail the build, and failing tests are already shown by their panics.
+        let mut diagnostics = vec![];
+        for diagnostic in blocks
+            .iter()
+            .flat_map(|block| rla::diagnostics::parse(&block.lines))
+        {
+            if matches!(diagnostic.kind, Kind::Warning | Kind::TestFailure) {
+                continue;
+            }
+            if !diagnostics.contains(&diagnostic) {
+                diagnostics.push(diagnostic);
+            }
+        }
+        diagnostics.truncate(MAX_DIAGNOSTICS);
+
+        // The occurrences of the same failure are identified by the line which classified it, or
+        // its most relevant diagnostic, as the context extracted around them varies between builds.
+        let sample = match (&matched, diagnostics.first()) {
+            (Some(m), _) => Some(m.line.clone()),
+            (None, Some(diagnostic)) => Some(diagnostic.to_string().into_bytes()),
+            (None, None) => blocks
+                .first()
+                .and_then(|block| block.lines.first())
+                .map(|line| line.sanitized.clone()),
         };
+        let fingerprint = sample
+            .as_ref()
+            .map(|sample| Fingerprint::new(index.normalizer(), &[Sanitized(sample)]));
+        let sample = sample.map_or(vec![], |sample| {
+            fingerprint::normalize(index.normalizer(), &sample)
+        });
+
+        let names = failed_tests.names();
+        let mut failed_tests = names
+            .iter()
+            .take(MAX_FAILED_TESTS)
+            .cloned()
+            .collect::<Vec<_>>();
+        if names.len() > MAX_FAILED_TESTS {
+            failed_tests.push(format!("... and {} more", names.len() - MAX_FAILED_TESTS));
+        }
 
-        let log_url = job.log_url().unwrap_or_else(|| "unknown".into());
-        self.github.post_comment(repo, pr, &format!(r#"
-{opening} failed! Check out the build log: [(web)]({html_url}){plain_enhanced} [(plain)]({log_url})
+        let metadata = self
+            .report_variables
+            .iter()
+            .filter_map(|name| Some((name.clone(), log_variables.get(name)?.to_string())))
+            .collect();
 
-<details><summary><i>Click to see the possible cause of the failure (guessed by this bot)</i></summary>
+        // Lead with the most likely cause of the failure, and drop or trim the least relevant
+        // blocks to keep the comment within GitHub's size limits.
+        let rendered = budget.apply(&blocks, self.line_output, &self.extract_config.garbage);
 
-```plain
-{log}
-```
+        let locations = rendered
+            .iter()
+            .map(|rendered| {
+                let block = &blocks[rendered.index];
+                let start = block.lines.first()?.number;
+                let end = block.lines.last()?.number;
+                let url = job.log_line_url(start)?;
+                Some(if start == end {
+                    format!("[L{start}]({url})")
+                } else {
+                    format!("[L{start}-L{end}]({url})")
+                })
+            })
+            .collect();
 
-</details>
-{trailer}"#, opening = opening, html_url = job.html_url(), plain_enhanced = plain_enhanced, log_url = log_url, log = extracted, trailer = trailer))?;
+        let blocks = rendered
+            .iter()
+            .map(|block| {
+                block
+                    .lines
+                    .iter()
+                    .map(|line| String::from_utf8_lossy(line).into_owned())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .collect();
+
+        Ok(JobReport {
+            job,
+            log_variables,
+            category,
+            signature: matched.map(|m| m.signature.pattern.as_str().to_string()),
+            fingerprint,
+            sample,
+            recurrence: None,
+            metadata,
+            failed_tests,
+            annotations,
+            diagnostics,
+            locations,
+            blocks,
+        })
+    }
 
-        info!("marked build {} as recently notified", build_id);
-        self.recently_notified.store(build_id);
+    /// Loads the log of the same job in the baseline `build`, if any. The baselines are cached
+    /// until a newer build of the baseline branch passes.
+    fn load_baseline(
+        &self,
+        build: &dyn rla::ci::Build,
+        job: &dyn rla::ci::Job,
+    ) -> rla::Result<Option<Arc<rla::baseline::Baseline>>> {
+        let head = match build.commit_sha() {
+            BuildCommit::Head { sha } | BuildCommit::Merge { sha } => sha.to_string(),
+        };
+        {
+            let cache = self.baselines.lock().unwrap();
+            if cache.head == head {
+                if let Some(baseline) = cache.jobs.get(job.name()) {
+                    return Ok(baseline.clone());
+                }
+            }
+        }
 
-        Ok(())
+        let baseline_job = build
+            .jobs()
+            .into_iter()
+            .find(|j| j.name() == job.name() && j.outcome().is_passed());
+        let baseline = match baseline_job {
+            Some(baseline_job) => {
+                debug!("using {} as the baseline", baseline_job);
+
+                let log = match ci::open_log(self.ci.as_ref(), baseline_job, self.github.internal())
+                {
+                    Some(res) => io::BufReader::new(res?),
+                    None => return Ok(None),
+                };
+
+                let mut baseline = rla::baseline::Baseline::new(self.index().normalizer().clone());
+                for line in rla::sanitize::read_lines(self.ci.as_ref(), log) {
+                    baseline.learn(&line?);
+                }
+                Some(Arc::new(baseline))
+            }
+            None => None,
+        };
+
+        let mut cache = self.baselines.lock().unwrap();
+        if cache.head != head {
+            *cache = BaselineCache {
+                head,
+                jobs: HashMap::new(),
+            };
+        }
+        cache.jobs.insert(job.name().to_string(), baseline.clone());
+        Ok(baseline)
     }
 
-    fn learn(&mut self, build: &dyn rla::ci::Build) -> rla::Result<()> {
+    fn learn(&self, build: &dyn rla::ci::Build) -> rla::Result<()> {
+        let mut learned = rla::Index::new(self.index().normalizer().clone());
+        // The jobs stay claimed until the index is updated, so that they're learned only once.
+        let mut learned_jobs = vec![];
         for job in &build.jobs() {
             if !job.outcome().is_passed() {
                 continue;
             }
 
-            if self.recently_learned.recently_witnessed(&job.id()) {
+            let Some(learning) = self.learning.claim(job.id()) else {
+                trace!("Skipped {} being processed by another worker", job);
+                continue;
+            };
+            if self
+                .recently_learned
+                .lock()
+                .unwrap()
+                .recently_witnessed(&job.id())
+            {
                 trace!("Skipped already processed {}", job);
                 continue;
             }
@@ -342,13 +710,10 @@ impl Worker {
 
             match ci::download_log(self.ci.as_ref(), *job, self.github.internal()) {
                 Some(Ok(log)) => {
-                    for line in rla::sanitize::split_lines(&log) {
-                        self.index.learn(
-                            &rla::index::Sanitized(rla::sanitize::clean(self.ci.as_ref(), line)),
-                            1,
-                        );
+                    for line in rla::sanitize::load_lines(self.ci.as_ref(), &log) {
+                        learned.learn(&line, 1);
                     }
-                    self.recently_learned.store(job.id());
+                    learned_jobs.push(learning);
                 }
                 None => {
                     warn!(
@@ -365,43 +730,91 @@ impl Worker {
             }
         }
 
+        if !learned_jobs.is_empty() {
+            self.update_index(learned)?;
+            let mut recently_learned = self.recently_learned.lock().unwrap();
+            for claim in &learned_jobs {
+                recently_learned.store(claim.key.clone());
+            }
+        }
+
         // To avoid persisting the index too many times to storage, we only persist it after some
         // time elapsed since the last save.
-        match self.last_index_backup {
+        let mut last_index_backup = self.last_index_backup.lock().unwrap();
+        match *last_index_backup {
             Some(last) if last.elapsed() >= MINIMUM_DELAY_BETWEEN_INDEX_BACKUPS => {
-                self.last_index_backup = Some(Instant::now());
-                self.index.save(&self.index_file)?;
+                *last_index_backup = Some(Instant::now());
+                self.save_index()?;
             }
             Some(_) => {}
-            None => self.last_index_backup = Some(Instant::now()),
+            None => *last_index_backup = Some(Instant::now()),
         }
 
         Ok(())
     }
 
+    /// The current snapshot of the index.
+    fn index(&self) -> Arc<rla::Index> {
+        self.index.read().unwrap().clone()
+    }
+
+    /// Adds the `learned` lines to a copy of the index, which replaces the snapshot once updated.
+    /// The extractions in progress keep using the previous snapshot.
+    fn update_index(&self, learned: rla::Index) -> rla::Result<()> {
+        let _updating = self.updating_index.lock().unwrap();
+        let mut index = rla::Index::clone(&self.index());
+        index.merge(learned)?;
+        *self.index.write().unwrap() = Arc::new(index);
+        Ok(())
+    }
+
+    pub fn save_index(&self) -> rla::Result<()> {
+        if self.dry_run {
+            info!("dry run, not saving the index");
+            return Ok(());
+        }
+        info!("persisting the index to disk");
+        self.index().save(&self.index_file)
+    }
+
     fn process_pr(&self, e: &rla::github::PullRequestEvent) -> rla::Result<()> {
         // Hide all comments by the bot when a new commit is pushed.
         if let rla::github::PullRequestAction::Synchronize = e.action {
-            self.github
-                .hide_own_comments(&e.repository.full_name, e.number)?;
+            if self.dry_run {
+                info!(
+                    "dry run, would have hidden the comments in {}#{}",
+                    e.repository.full_name, e.number
+                );
+            } else {
+                self.github
+                    .hide_own_comments(&e.repository.full_name, e.number)?;
+            }
         }
         Ok(())
     }
 }
 
+/// The baselines loaded from the same build of the baseline branch, by job name.
+#[derive(Default)]
+struct BaselineCache {
+    /// Commit of the build the baselines were loaded from.
+    head: String,
+    jobs: HashMap<String, Option<Arc<rla::baseline::Baseline>>>,
+}
+
 /// Keeps track of the recently seen IDs for both the failed build reports and the learned jobs.
 /// Only the most recent IDs are stored, to avoid growing the memory usage endlessly.
 ///
 /// Internally this uses both an HashSet to provide fast lookups and a VecDeque to know which old
 /// jobs needs to be removed.
-struct RecentlySeen<T: Clone + Eq + Hash> {
+pub(super) struct RecentlySeen<T: Clone + Eq + Hash> {
     size: usize,
     lookup: HashSet<T>,
     removal: VecDeque<T>,
 }
 
 impl<T: Clone + Eq + Hash> RecentlySeen<T> {
-    fn new(size: usize) -> Self {
+    pub(super) fn new(size: usize) -> Self {
         Self {
             size,
             lookup: HashSet::with_capacity(size),
@@ -409,11 +822,11 @@ impl<T: Clone + Eq + Hash> RecentlySeen<T> {
         }
     }
 
-    fn recently_witnessed(&self, key: &T) -> bool {
+    pub(super) fn recently_witnessed(&self, key: &T) -> bool {
         self.lookup.contains(key)
     }
 
-    fn store(&mut self, key: T) {
+    pub(super) fn store(&mut self, key: T) {
         if self.lookup.contains(&key) {
             return;
         }
@@ -427,6 +840,40 @@ impl<T: Clone + Eq + Hash> RecentlySeen<T> {
     }
 }
 
+/// Keys of the builds or jobs being processed by one of the workers, to avoid processing them twice
+/// at the same time.
+pub(super) struct InProgress<T: Clone + Eq + Hash> {
+    keys: Mutex<HashSet<T>>,
+}
+
+impl<T: Clone + Eq + Hash> InProgress<T> {
+    pub(super) fn new() -> Self {
+        Self {
+            keys: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Marks `key` as being processed until the returned guard is dropped, or returns `None` if
+    /// it's already being processed.
+    pub(super) fn claim(&self, key: T) -> Option<Claim<'_, T>> {
+        if !self.keys.lock().unwrap().insert(key.clone()) {
+            return None;
+        }
+        Some(Claim { owner: self, key })
+    }
+}
+
+pub(super) struct Claim<'a, T: Clone + Eq + Hash> {
+    owner: &'a InProgress<T>,
+    key: T,
+}
+
+impl<T: Clone + Eq + Hash> Drop for Claim<'_, T> {
+    fn drop(&mut self) {
+        self.owner.keys.lock().unwrap().remove(&self.key);
+    }
+}
+
 enum ProcessOutcome {
     Continue,
     Exit,
@@ -459,4 +906,16 @@ mod tests {
         assert!(recently.recently_witnessed(&1));
         assert!(recently.recently_witnessed(&2));
     }
+
+    #[test]
+    fn test_in_progress() {
+        let in_progress = InProgress::new();
+
+        let claim = in_progress.claim(1).unwrap();
+        assert!(in_progress.claim(1).is_none());
+        assert!(in_progress.claim(2).is_some());
+
+        drop(claim);
+        assert!(in_progress.claim(1).is_some());
+    }
 }
@@ -8,7 +8,7 @@ pub(crate) enum CliCiPlatform {
 }
 
 impl CliCiPlatform {
-    pub(crate) fn get(&self) -> rla::Result<Box<dyn rla::ci::CiPlatform + Send>> {
+    pub(crate) fn get(&self) -> rla::Result<Box<dyn rla::ci::CiPlatform + Send + Sync>> {
         Ok(match self {
             CliCiPlatform::Azure => {
                 let token = std::env::var("AZURE_DEVOPS_TOKEN")
@@ -36,6 +36,41 @@ impl std::str::FromStr for CliCiPlatform {
     }
 }
 
+/// Thresholds of the detection of binary or encoded data in the logs, which is elided.
+#[derive(clap::Args, Debug)]
+pub(crate) struct GarbageArgs {
+    #[arg(
+        long = "garbage-max-line-length",
+        help = "Lines longer than this many bytes are considered binary or encoded data. Defaults to 4096."
+    )]
+    max_line_length: Option<usize>,
+    #[arg(
+        long = "garbage-max-word-entropy",
+        help = "Words with at least this entropy, in bits per byte, are considered encoded data. Defaults to 5.0."
+    )]
+    max_word_entropy: Option<f64>,
+    #[arg(
+        long = "no-garbage-filter",
+        conflicts_with_all = ["max_line_length", "max_word_entropy"],
+        help = "Don't detect binary or encoded data in the logs."
+    )]
+    no_garbage_filter: bool,
+}
+
+impl GarbageArgs {
+    pub(crate) fn filter(&self) -> rla::garbage::GarbageFilter {
+        if self.no_garbage_filter {
+            return rla::garbage::GarbageFilter::disabled();
+        }
+        let default = rla::garbage::GarbageFilter::default();
+        rla::garbage::GarbageFilter {
+            max_line_length: self.max_line_length.unwrap_or(default.max_line_length),
+            max_word_entropy: self.max_word_entropy.unwrap_or(default.max_word_entropy),
+            ..default
+        }
+    }
+}
+
 pub fn run<F: FnOnce() -> rla::Result<()>>(f: F) {
     tracing_subscriber::fmt()
         .with_writer(std::io::stderr)
@@ -43,7 +78,7 @@ pub fn run<F: FnOnce() -> rla::Result<()>>(f: F) {
         .with_env_filter(tracing_subscriber::EnvFilter::from_env("RLA_LOG"))
         .init();
 
-    log_and_exit_error(|| f());
+    log_and_exit_error(f);
 }
 
 pub fn log_and_exit_error<F: FnOnce() -> rla::Result<()>>(f: F) {
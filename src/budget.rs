@@ -0,0 +1,316 @@
+use crate::extract::{Block, LineOutput};
+use crate::garbage::GarbageFilter;
+use crate::index::IndexData;
+
+/// Separator written between two blocks, which also counts towards the budget.
+pub const BLOCK_SEPARATOR: &[u8] = b"---";
+/// Lines longer than this are truncated when their block doesn't fit in the budget.
+const MAX_LINE_BYTES: usize = 1000;
+
+/// A block rendered by [`Budget::apply`].
+pub struct RenderedBlock {
+    /// Position of the block in the blocks given to [`Budget::apply`].
+    pub index: usize,
+    pub lines: Vec<Vec<u8>>,
+}
+
+/// Limits on the total size of the extracted output, applied across all the blocks.
+pub struct Budget {
+    pub max_blocks: usize,
+    pub max_lines: usize,
+    pub max_bytes: usize,
+    /// Blocks which can't keep at least this many lines are dropped instead of being trimmed.
+    pub min_block_lines: usize,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            max_blocks: 5,
+            max_lines: 500,
+            // GitHub rejects comments longer than 65536 characters, leave some room for the rest
+            // of the comment.
+            max_bytes: 60_000,
+            min_block_lines: 10,
+        }
+    }
+}
+
+impl Budget {
+    /// A budget only limiting the number of lines and bytes, if given.
+    pub fn new(max_lines: Option<usize>, max_bytes: Option<usize>) -> Self {
+        Budget {
+            max_blocks: usize::MAX,
+            max_lines: max_lines.unwrap_or(usize::MAX),
+            max_bytes: max_bytes.unwrap_or(usize::MAX),
+            ..Budget::default()
+        }
+    }
+
+    /// Divides the budget in `parts` equal budgets, e.g. to share it between multiple logs.
+    pub fn split(&self, parts: usize) -> Budget {
+        let parts = parts.max(1);
+        Budget {
+            max_blocks: self.max_blocks,
+            max_lines: self.max_lines / parts,
+            max_bytes: self.max_bytes / parts,
+            min_block_lines: self.min_block_lines,
+        }
+    }
+
+    /// Renders the `blocks` (expected to be sorted by relevance) until the budget is exhausted.
+    /// Blocks too long to fit entirely have their longest lines truncated and their middle lines
+    /// replaced by a marker, and are skipped if they still don't fit.
+    pub fn apply<I: IndexData>(
+        &self,
+        blocks: &[Block<I>],
+        output: LineOutput,
+        garbage: &GarbageFilter,
+    ) -> Vec<RenderedBlock> {
+        let mut lines_left = self.max_lines;
+        let mut bytes_left = self.max_bytes;
+        let mut result = vec![];
+
+        for (index, block) in blocks.iter().enumerate().take(self.max_blocks) {
+            let mut rendered = block
+                .lines
+                .iter()
+                .map(|line| output.render(line, garbage))
+                .collect::<Vec<_>>();
+
+            // Account for the separator from the previous block.
+            let (separator_lines, separator_bytes) = if result.is_empty() {
+                (0, 0)
+            } else {
+                (1, line_size(BLOCK_SEPARATOR))
+            };
+            let (Some(max_lines), Some(max_bytes)) = (
+                lines_left.checked_sub(separator_lines),
+                bytes_left.checked_sub(separator_bytes),
+            ) else {
+                break;
+            };
+
+            if rendered.len() > max_lines || total_size(&rendered) > max_bytes {
+                for line in &mut rendered {
+                    truncate_line(line);
+                }
+            }
+            if rendered.len() > max_lines || total_size(&rendered) > max_bytes {
+                // Lower-ranked blocks might still fit.
+                match self.elide(rendered, max_lines, max_bytes) {
+                    Some(elided) => rendered = elided,
+                    None => continue,
+                }
+            }
+
+            lines_left = max_lines - rendered.len();
+            bytes_left = max_bytes - total_size(&rendered);
+            result.push(RenderedBlock {
+                index,
+                lines: rendered,
+            });
+        }
+
+        result
+    }
+
+    /// Replaces the middle lines of the block with a marker, keeping as many lines as possible
+    /// from both ends while staying within the limits.
+    fn elide(
+        &self,
+        lines: Vec<Vec<u8>>,
+        max_lines: usize,
+        max_bytes: usize,
+    ) -> Option<Vec<Vec<u8>>> {
+        // One of the lines is used by the marker.
+        let mut kept = lines.len().min(max_lines.saturating_sub(1));
+
+        while kept >= self.min_block_lines {
+            let head = kept.div_ceil(2);
+            let tail = kept - head;
+            let marker = format!("[... {} lines omitted ...]", lines.len() - kept).into_bytes();
+
+            let size = total_size(&lines[..head])
+                + line_size(&marker)
+                + total_size(&lines[lines.len() - tail..]);
+            if size <= max_bytes {
+                let mut elided = lines[..head].to_vec();
+                elided.push(marker);
+                elided.extend_from_slice(&lines[lines.len() - tail..]);
+                return Some(elided);
+            }
+
+            kept -= 1;
+        }
+
+        None
+    }
+}
+
+/// Truncates the `line` to `MAX_LINE_BYTES` if it's longer, followed by a marker.
+fn truncate_line(line: &mut Vec<u8>) {
+    if line.len() <= MAX_LINE_BYTES {
+        return;
+    }
+    // Avoid cutting a character in half.
+    let mut kept = MAX_LINE_BYTES;
+    while kept > 0 && (line[kept] & 0b1100_0000) == 0b1000_0000 {
+        kept -= 1;
+    }
+    let omitted = line.len() - kept;
+    line.truncate(kept);
+    line.extend_from_slice(format!(" [... {omitted} bytes omitted ...]").as_bytes());
+}
+
+/// Size of a line once written, including the line break.
+fn line_size(line: &[u8]) -> usize {
+    line.len() + 1
+}
+
+fn total_size(lines: &[Vec<u8>]) -> usize {
+    lines.iter().map(|line| line_size(line)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::Trigger;
+    use crate::index::Sanitized;
+
+    fn block(lines: &[Sanitized<String>]) -> Block<&Sanitized<String>> {
+        Block {
+            position: 0,
+            start: 0,
+            end: lines.len(),
+            lines: lines.iter().collect(),
+            trigger: Trigger::UniqueLine { line: 0, score: 0 },
+            relevance: 0,
+            total_score: 0,
+            max_score: 0,
+            anchor_hits: 0,
+        }
+    }
+
+    fn lines(prefix: &str, count: usize) -> Vec<Sanitized<String>> {
+        (0..count)
+            .map(|i| Sanitized(format!("{prefix}{i}")))
+            .collect()
+    }
+
+    fn render(blocks: Vec<RenderedBlock>) -> Vec<Vec<String>> {
+        blocks
+            .into_iter()
+            .map(|block| {
+                block
+                    .lines
+                    .into_iter()
+                    .map(|line| String::from_utf8(line).unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_budget_fits() {
+        let first = lines("a", 3);
+        let second = lines("b", 2);
+        let budget = Budget::new(Some(6), None);
+
+        assert_eq!(
+            vec![vec!["a0", "a1", "a2"], vec!["b0", "b1"]],
+            render(budget.apply(
+                &[block(&first), block(&second)],
+                LineOutput::Sanitized,
+                &GarbageFilter::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_budget_elides_middle() {
+        let first = lines("a", 30);
+        let budget = Budget::new(Some(11), None);
+
+        assert_eq!(
+            vec![vec![
+                "a0",
+                "a1",
+                "a2",
+                "a3",
+                "a4",
+                "[... 20 lines omitted ...]",
+                "a25",
+                "a26",
+                "a27",
+                "a28",
+                "a29",
+            ]],
+            render(budget.apply(
+                &[block(&first)],
+                LineOutput::Sanitized,
+                &GarbageFilter::default()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_budget_drops_blocks() {
+        let first = lines("a", 20);
+        let second = lines("b", 20);
+        let budget = Budget::new(None, Some(20 * 4 + 15));
+
+        let result = render(budget.apply(
+            &[block(&first), block(&second)],
+            LineOutput::Sanitized,
+            &GarbageFilter::default(),
+        ));
+        assert_eq!(1, result.len());
+        assert_eq!(20, result[0].len());
+    }
+
+    #[test]
+    fn test_budget_skips_blocks() {
+        let first = lines("a", 20);
+        let second = lines("b", 3);
+        let third = lines("c", 2);
+        // The second block is too short to be elided, but the third one fits.
+        let budget = Budget::new(Some(20 + 1 + 2), None);
+
+        let result = budget.apply(
+            &[block(&first), block(&second), block(&third)],
+            LineOutput::Sanitized,
+            &GarbageFilter::default(),
+        );
+        assert_eq!(
+            vec![0, 2],
+            result.iter().map(|block| block.index).collect::<Vec<_>>()
+        );
+        assert_eq!(vec!["c0", "c1"], render(result)[1]);
+    }
+
+    #[test]
+    fn test_budget_truncates_long_lines() {
+        let long = vec![Sanitized("word ".repeat(20_000))];
+        let budget = Budget::new(None, Some(2_000));
+
+        // Not elided as garbage, e.g. when the filter is disabled.
+        let result = render(budget.apply(
+            &[block(&long)],
+            LineOutput::Sanitized,
+            &GarbageFilter::disabled(),
+        ));
+        assert_eq!(1, result.len());
+        assert!(result[0][0].ends_with(" [... 99000 bytes omitted ...]"));
+    }
+
+    #[test]
+    fn test_budget_split() {
+        let budget = Budget::default().split(3);
+        assert_eq!(166, budget.max_lines);
+        assert_eq!(20_000, budget.max_bytes);
+        assert_eq!(Budget::default().max_blocks, budget.max_blocks);
+
+        assert_eq!(500, Budget::default().split(0).max_lines);
+    }
+}
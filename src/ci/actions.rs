@@ -12,6 +12,7 @@ struct ActionsRun {
     id: u64,
     head_branch: String,
     head_sha: String,
+    workflow_id: u64,
     #[serde(flatten)]
     outcome: BuildOutcome,
 }
@@ -73,6 +74,10 @@ impl Build for GHABuild {
     fn jobs(&self) -> Vec<&dyn Job> {
         self.jobs.iter().map(|j| j as &dyn Job).collect()
     }
+
+    fn workflow_id(&self) -> Option<u64> {
+        Some(self.run.workflow_id)
+    }
 }
 
 #[derive(Deserialize)]
@@ -95,6 +100,10 @@ impl Job for GHAJob {
         self.inner.id.to_string()
     }
 
+    fn name(&self) -> &str {
+        &self.inner.name
+    }
+
     fn html_url(&self) -> String {
         self.inner.html_url.clone()
     }
@@ -120,6 +129,10 @@ impl Job for GHAJob {
         ))
     }
 
+    fn log_line_url(&self, line: usize) -> Option<String> {
+        self.log_enhanced_url().map(|url| format!("{url}#L{line}"))
+    }
+
     fn log_file_name(&self) -> String {
         format!("actions-{}-{}", self.inner.id, self.inner.name)
     }
@@ -252,7 +265,39 @@ impl CiPlatform for Client {
             .req(Method::GET, &format!("repos/{}/actions/runs/{}", repo, id))?
             .error_for_status()?
             .json()?;
-        Ok(GHABuild::new(self, repo, run)?)
+        GHABuild::new(self, repo, run)
+    }
+
+    fn query_latest_passed_build(
+        &self,
+        repo: &str,
+        branch: &str,
+        like: &dyn Build,
+    ) -> Result<Option<Box<dyn Build>>> {
+        #[derive(Deserialize)]
+        struct BranchRuns {
+            workflow_runs: Vec<ActionsRun>,
+        }
+
+        // Filtering on the API side avoids querying the jobs of all the runs of the repository.
+        // The runs of the other workflows don't contain the same jobs.
+        let runs = match like.workflow_id() {
+            Some(id) => format!("repos/{}/actions/workflows/{}/runs", repo, id),
+            None => format!("repos/{}/actions/runs", repo),
+        };
+        let url = reqwest::Url::parse_with_params(
+            &format!("https://api.github.com/{}", runs),
+            &[("branch", branch), ("status", "success"), ("per_page", "1")],
+        )?;
+        let runs: BranchRuns = self
+            .req(Method::GET, url.as_str())?
+            .error_for_status()?
+            .json()?;
+        runs.workflow_runs
+            .into_iter()
+            .next()
+            .map(|run| GHABuild::new(self, repo, run))
+            .transpose()
     }
 
     fn remove_timestamp_from_log_line<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
@@ -266,7 +311,7 @@ impl CiPlatform for Client {
                 reqwest::header::AUTHORIZATION,
                 format!("token {}", self.token),
             )
-            .header(reqwest::header::USER_AGENT, format!("rust-log-analyzer"))
+            .header(reqwest::header::USER_AGENT, "rust-log-analyzer")
     }
 
     fn is_build_outcome_unreliable(&self) -> bool {
@@ -97,6 +97,10 @@ impl Job for AzureJob {
         self.record.id.clone()
     }
 
+    fn name(&self) -> &str {
+        &self.record.name
+    }
+
     fn html_url(&self) -> String {
         format!(
             "https://dev.azure.com/{repo}/_build/results?buildId={build}&view=logs&jobId={job}",
@@ -213,7 +217,7 @@ impl Build for AzureBuild {
     fn branch_name(&self) -> &str {
         const HEAD_PREFIX: &str = "refs/heads/";
         if let Some(branch) = &self.data.trigger_info.pr_branch {
-            &branch
+            branch
         } else if self.data.source_branch.starts_with(HEAD_PREFIX) {
             &self.data.source_branch[HEAD_PREFIX.len()..]
         } else {
@@ -323,7 +327,7 @@ impl CiPlatform for Client {
             if build.outcome.status == Some(BuildStatus::InProgress) {
                 continue;
             }
-            if let Some(build) = AzureBuild::new(&self, repo, build)? {
+            if let Some(build) = AzureBuild::new(self, repo, build)? {
                 println!(
                     "id={} pr={:?} branch_name={}, commit={}, status={:?}",
                     build.data.id,
@@ -351,7 +355,7 @@ impl CiPlatform for Client {
         )?;
         let mut resp = resp.error_for_status()?;
         let data: AzureBuildData = resp.json()?;
-        if let Some(build) = AzureBuild::new(&self, repo, data)? {
+        if let Some(build) = AzureBuild::new(self, repo, data)? {
             Ok(Box::new(build))
         } else {
             Err(anyhow::anyhow!("no build results"))
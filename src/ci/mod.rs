@@ -26,13 +26,21 @@ pub trait Outcome: std::fmt::Debug {
 pub trait Build {
     fn pr_number(&self) -> Option<u32>;
     fn branch_name(&self) -> &str;
-    fn commit_sha(&self) -> BuildCommit;
+    fn commit_sha(&self) -> BuildCommit<'_>;
     fn outcome(&self) -> &dyn Outcome;
     fn jobs(&self) -> Vec<&dyn Job>;
+
+    /// The workflow the build is a run of, if the CI platform runs different sets of jobs on the
+    /// same branch.
+    fn workflow_id(&self) -> Option<u64> {
+        None
+    }
 }
 
 pub trait Job: std::fmt::Display {
     fn id(&self) -> String;
+    /// Name of the job, shared by the runs of the same job across builds.
+    fn name(&self) -> &str;
     fn html_url(&self) -> String;
     fn log_url(&self) -> Option<String>; // sometimes we just don't have log URLs
     fn log_file_name(&self) -> String;
@@ -45,6 +53,11 @@ pub trait Job: std::fmt::Display {
     fn log_enhanced_url(&self) -> Option<String> {
         None
     }
+
+    /// URL pointing to the given 1-based line of the log in the web UI, if supported.
+    fn log_line_url(&self, _line: usize) -> Option<String> {
+        None
+    }
 }
 
 pub trait CiPlatform {
@@ -60,6 +73,23 @@ pub trait CiPlatform {
     ) -> Result<Vec<Box<dyn Build>>>;
     fn query_build(&self, repo: &str, id: u64) -> Result<Box<dyn Build>>;
 
+    /// The latest passed build of `branch` running the same workflow as `like`, if any.
+    fn query_latest_passed_build(
+        &self,
+        repo: &str,
+        branch: &str,
+        like: &dyn Build,
+    ) -> Result<Option<Box<dyn Build>>> {
+        Ok(self
+            .query_builds(repo, 1, 0, &|build| {
+                build.branch_name() == branch
+                    && build.workflow_id() == like.workflow_id()
+                    && build.outcome().is_passed()
+            })?
+            .into_iter()
+            .next())
+    }
+
     fn remove_timestamp_from_log_line<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
         Cow::Borrowed(line)
     }
@@ -80,8 +110,28 @@ pub fn download_log(
     job: &dyn Job,
     client: &reqwest::blocking::Client,
 ) -> Option<Result<Vec<u8>>> {
+    let mut resp = match open_log(ci, job, client)? {
+        Ok(v) => v,
+        Err(e) => return Some(Err(e)),
+    };
+
+    let mut bytes: Vec<u8> = vec![];
+    if let Err(err) = resp.read_to_end(&mut bytes) {
+        return Some(Err(err.into()));
+    }
+
+    Some(Ok(bytes))
+}
+
+/// Like [`download_log`], but returns a reader over the log instead of loading all of it in
+/// memory.
+pub fn open_log(
+    ci: &dyn CiPlatform,
+    job: &dyn Job,
+    client: &reqwest::blocking::Client,
+) -> Option<Result<reqwest::blocking::Response>> {
     if let Some(url) = job.log_api_url() {
-        let mut resp = match ci.authenticate_request(client.get(&url)).send() {
+        let resp = match ci.authenticate_request(client.get(&url)).send() {
             Ok(v) => v,
             Err(e) => return Some(Err(e.into())),
         };
@@ -90,12 +140,7 @@ pub fn download_log(
             return Some(Err(anyhow!("Downloading log failed: {:?}", resp)));
         }
 
-        let mut bytes: Vec<u8> = vec![];
-        if let Err(err) = resp.read_to_end(&mut bytes) {
-            return Some(Err(err.into()));
-        }
-
-        return Some(Ok(bytes));
+        return Some(Ok(resp));
     }
 
     None
@@ -0,0 +1,258 @@
+use crate::index::IndexData;
+use regex::bytes::Regex;
+use std::fmt;
+use std::path::Path;
+
+/// Built-in signatures, by decreasing priority. Problems with the environment come first, as they
+/// often cause compile errors or test failures as a side effect.
+static SIGNATURES: &[(Category, &str)] = &[
+    // GitHub Actions runners
+    (
+        Category::Infrastructure,
+        "The runner has received a shutdown signal",
+    ),
+    (
+        Category::Infrastructure,
+        "lost communication with the server",
+    ),
+    (Category::Infrastructure, "No space left on device"),
+    (
+        Category::ResourceExhausted,
+        "has exceeded the maximum execution time",
+    ),
+    (
+        Category::ResourceExhausted,
+        "memory allocation of [0-9]+ bytes failed",
+    ),
+    (Category::ResourceExhausted, "(?i)out of memory"),
+    (Category::ResourceExhausted, "signal: 9, SIGKILL"),
+    (Category::Network, "Could not resolve host"),
+    (Category::Network, "Connection reset by peer"),
+    (Category::Network, "Connection timed out"),
+    (Category::Network, "Operation timed out"),
+    (Category::Network, "curl: \\([0-9]+\\)"),
+    (Category::Network, "failed to download"),
+    (Category::Network, "spurious network error"),
+    (
+        Category::Network,
+        "50[234] (Bad Gateway|Service Unavailable|Gateway Time-?out)",
+    ),
+    (Category::Tidy, "tidy error:"),
+    (Category::Tidy, "some tidy checks failed"),
+    (Category::TestFailure, "test result: FAILED"),
+    (Category::TestFailure, "^---- .* stdout ----$"),
+    (Category::TestFailure, "error: test failed"),
+    (Category::CompileError, "error\\[E[0-9]+\\]"),
+    (Category::CompileError, "error: could not compile"),
+    (Category::CompileError, "error: aborting due to"),
+];
+
+/// What kind of problem caused a build to fail.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    CompileError,
+    TestFailure,
+    Tidy,
+    Network,
+    /// The job ran out of memory or time.
+    ResourceExhausted,
+    /// The CI runner itself failed.
+    Infrastructure,
+    /// None of the signatures matched.
+    Unknown,
+}
+
+impl Category {
+    /// Human readable description of the category, as shown in the reports.
+    pub fn description(self) -> &'static str {
+        match self {
+            Category::CompileError => "compile error",
+            Category::TestFailure => "test failure",
+            Category::Tidy => "tidy failure",
+            Category::Network => "network error",
+            Category::ResourceExhausted => "timeout or out of memory",
+            Category::Infrastructure => "CI infrastructure problem",
+            Category::Unknown => "unknown failure",
+        }
+    }
+
+    /// Whether failures of this category are usually unrelated to the changes being tested, and
+    /// are likely to go away when the job is retried.
+    pub fn is_spurious(self) -> bool {
+        matches!(self, Category::Network | Category::Infrastructure)
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+/// A pattern which, when found in an extracted line, assigns the given category to the failure.
+pub struct Signature {
+    pub category: Category,
+    pub pattern: Regex,
+}
+
+/// The signature used to classify a failure, along with the line it matched.
+pub struct Match<'a> {
+    pub signature: &'a Signature,
+    pub line: Vec<u8>,
+}
+
+/// Assigns a [`Category`] to failures based on the lines extracted from their log.
+pub struct Classifier {
+    /// Signatures by decreasing priority.
+    signatures: Vec<Signature>,
+}
+
+impl Default for Classifier {
+    fn default() -> Self {
+        Classifier {
+            signatures: SIGNATURES
+                .iter()
+                .map(|&(category, pattern)| Signature {
+                    category,
+                    pattern: Regex::new(pattern).unwrap(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Classifier {
+    /// The built-in signatures, preceded by the ones defined in the given JSON file. The file
+    /// contains a list of objects, each with a `category` and a `pattern` regex.
+    pub fn load(path: &Path) -> crate::Result<Self> {
+        #[derive(Deserialize)]
+        struct RawSignature {
+            category: Category,
+            pattern: String,
+        }
+
+        let raw: Vec<RawSignature> = serde_json::from_slice(&std::fs::read(path)?)?;
+        let signatures = raw
+            .into_iter()
+            .map(|raw| {
+                Ok(Signature {
+                    category: raw.category,
+                    pattern: Regex::new(&raw.pattern)?,
+                })
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+
+        Ok(Classifier::default().with_signatures(signatures))
+    }
+
+    /// Adds `signatures` with a higher priority than the existing ones.
+    pub fn with_signatures(mut self, mut signatures: Vec<Signature>) -> Self {
+        signatures.append(&mut self.signatures);
+        self.signatures = signatures;
+        self
+    }
+
+    /// Returns the category of the highest priority signature matching any of the `lines`.
+    pub fn classify<'a, I: IndexData + 'a>(
+        &self,
+        lines: impl IntoIterator<Item = &'a I>,
+    ) -> Category {
+        self.find_match(lines)
+            .map_or(Category::Unknown, |m| m.signature.category)
+    }
+
+    /// Returns the highest priority signature matching any of the `lines`, if any.
+    pub fn find_match<'a, I: IndexData + 'a>(
+        &self,
+        lines: impl IntoIterator<Item = &'a I>,
+    ) -> Option<Match<'_>> {
+        let mut best: Option<(usize, Vec<u8>)> = None;
+
+        for line in lines {
+            let candidates = best
+                .as_ref()
+                .map_or(self.signatures.len(), |&(priority, _)| priority);
+            let sanitized = line.sanitized();
+            if let Some(priority) = self.signatures[..candidates]
+                .iter()
+                .position(|signature| signature.pattern.is_match(sanitized))
+            {
+                best = Some((priority, sanitized.to_vec()));
+                if priority == 0 {
+                    break;
+                }
+            }
+        }
+
+        best.map(|(priority, line)| Match {
+            signature: &self.signatures[priority],
+            line,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Sanitized;
+
+    fn classify(classifier: &Classifier, lines: &[&str]) -> Category {
+        classifier.classify(&lines.iter().map(Sanitized).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_classify() {
+        let classifier = Classifier::default();
+
+        assert_eq!(
+            classify(&classifier, &["error[E0308]: mismatched types"]),
+            Category::CompileError
+        );
+        assert_eq!(
+            classify(
+                &classifier,
+                &[
+                    "---- [ui] tests/ui/foo.rs stdout ----",
+                    "test result: FAILED. 1 passed; 1 failed"
+                ]
+            ),
+            Category::TestFailure
+        );
+        assert_eq!(
+            classify(&classifier, &["everything is fine"]),
+            Category::Unknown
+        );
+    }
+
+    #[test]
+    fn test_classify_priority() {
+        let lines = [
+            "error: failed to download `foo v1.0.0`",
+            "error: could not compile `bar`",
+        ];
+        assert_eq!(classify(&Classifier::default(), &lines), Category::Network);
+
+        let classifier = Classifier::default().with_signatures(vec![Signature {
+            category: Category::CompileError,
+            pattern: Regex::new("could not compile `bar`").unwrap(),
+        }]);
+        assert_eq!(classify(&classifier, &lines), Category::CompileError);
+    }
+
+    #[test]
+    fn test_find_spurious_match() {
+        let lines = [
+            "error: failed to get `foo` as a dependency",
+            "curl: (28) Operation timed out after 30000 milliseconds",
+        ];
+        let classifier = Classifier::default();
+        let m = classifier
+            .find_match(&lines.iter().map(Sanitized).collect::<Vec<_>>())
+            .unwrap();
+
+        assert_eq!(m.signature.category, Category::Network);
+        assert!(m.signature.category.is_spurious());
+        assert_eq!(m.line, lines[1].as_bytes());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::index::IndexData;
+use regex::Regex;
+use std::fmt;
+
+lazy_static! {
+    /// `error[E0308]: mismatched types`, as emitted by rustc and cargo.
+    static ref RUSTC_MESSAGE: Regex =
+        Regex::new(r"^(error|warning)(?:\[(E[0-9]{4})\])?: (.+)$").unwrap();
+    /// `  --> src/lib.rs:10:5`, the location of the previous rustc message.
+    static ref RUSTC_LOCATION: Regex = Regex::new(r"^-+> (.+):([0-9]+):([0-9]+)$").unwrap();
+    /// `---- [ui] tests/ui/foo.rs stdout ----` for compiletest, `---- foo::bar stdout ----` for
+    /// libtest.
+    static ref TEST_OUTPUT: Regex =
+        Regex::new(r"^---- (?:\[([\w-]+)\] )?(\S+)(?: \S+)* stdout ----$").unwrap();
+    /// `thread 'foo::bar' panicked at src/lib.rs:10:5:`, the message being on the next line.
+    static ref PANIC: Regex =
+        Regex::new(r"^thread '([^']*)' panicked at (.+):([0-9]+):([0-9]+):(?: (.+))?$").unwrap();
+    /// `thread 'foo::bar' panicked at 'message', src/lib.rs:10:5`, before Rust 1.73.
+    static ref OLD_PANIC: Regex =
+        Regex::new(r"^thread '([^']*)' panicked at '(.*)', (.+):([0-9]+):([0-9]+)$").unwrap();
+    /// `tidy error: /checkout/src/foo.rs:12: trailing whitespace`
+    static ref TIDY: Regex = Regex::new(r"^tidy error: (.+)$").unwrap();
+    static ref TIDY_LOCATION: Regex = Regex::new(r"^(\S+?):([0-9]+): (.+)$").unwrap();
+}
+
+/// Rustc messages which only summarize the other diagnostics.
+static IGNORED_MESSAGES: &[&str] = &["aborting due to"];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Error,
+    Warning,
+    /// A test reported as failed by libtest or compiletest.
+    TestFailure,
+    Panic,
+    Tidy,
+}
+
+/// A diagnostic recognized in the log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub kind: Kind,
+    /// Error code of rustc errors, e.g. `E0308`.
+    pub code: Option<String>,
+    pub message: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+    /// The failing test, e.g. `[ui] tests/ui/foo.rs` or `foo::tests::bar`.
+    pub test: Option<String>,
+}
+
+impl Diagnostic {
+    fn new(kind: Kind) -> Self {
+        Diagnostic {
+            kind,
+            code: None,
+            message: None,
+            file: None,
+            line: None,
+            column: None,
+            test: None,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Kind::Error => f.write_str("error")?,
+            Kind::Warning => f.write_str("warning")?,
+            Kind::TestFailure => f.write_str("test failed")?,
+            Kind::Panic => f.write_str("panic")?,
+            Kind::Tidy => f.write_str("tidy error")?,
+        }
+        if let Some(ref code) = self.code {
+            write!(f, "[{}]", code)?;
+        }
+        if let Some(ref test) = self.test {
+            write!(f, " in {}", test)?;
+        }
+        if let Some(ref message) = self.message {
+            write!(f, ": {}", message)?;
+        }
+        if let Some(ref file) = self.file {
+            write!(f, " at {}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+            }
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Recognizes the rustc and cargo diagnostics, test failures, panics and tidy errors in the
+/// `lines`. The lines are expected to be contiguous, e.g. the lines of an extracted block.
+pub fn parse<'a, I: IndexData + 'a>(lines: impl IntoIterator<Item = &'a I>) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    // Whether the last diagnostic can still receive its location or message from the next line.
+    let mut pending = false;
+
+    for line in lines {
+        let line = String::from_utf8_lossy(line.sanitized());
+        let line = line.trim();
+        let was_pending = std::mem::replace(&mut pending, false);
+
+        if let Some(c) = RUSTC_MESSAGE.captures(line) {
+            let message = &c[3];
+            if IGNORED_MESSAGES.iter().any(|m| message.starts_with(m)) {
+                continue;
+            }
+
+            let mut diagnostic = Diagnostic::new(if &c[1] == "error" {
+                Kind::Error
+            } else {
+                Kind::Warning
+            });
+            diagnostic.code = c.get(2).map(|m| m.as_str().to_string());
+            diagnostic.message = Some(message.to_string());
+            diagnostics.push(diagnostic);
+            pending = true;
+        } else if let Some(c) = RUSTC_LOCATION.captures(line) {
+            match diagnostics.last_mut() {
+                Some(last) if was_pending => set_location(last, &c[1], &c[2], Some(&c[3])),
+                _ => {}
+            }
+        } else if let Some(c) = TEST_OUTPUT.captures(line) {
+            let mut diagnostic = Diagnostic::new(Kind::TestFailure);
+            diagnostic.test = Some(match c.get(1) {
+                Some(suite) => {
+                    // Strip the revision, e.g. `#edition2021`.
+                    let file = c[2].split('#').next().unwrap();
+                    diagnostic.file = Some(file.to_string());
+                    format!("[{}] {}", suite.as_str(), &c[2])
+                }
+                None => c[2].to_string(),
+            });
+            diagnostics.push(diagnostic);
+        } else if let Some(c) = PANIC.captures(line) {
+            let mut diagnostic = Diagnostic::new(Kind::Panic);
+            diagnostic.test = panicking_test(&c[1]);
+            set_location(&mut diagnostic, &c[2], &c[3], Some(&c[4]));
+            diagnostic.message = c.get(5).map(|m| m.as_str().to_string());
+            pending = diagnostic.message.is_none();
+            diagnostics.push(diagnostic);
+        } else if let Some(c) = OLD_PANIC.captures(line) {
+            let mut diagnostic = Diagnostic::new(Kind::Panic);
+            diagnostic.test = panicking_test(&c[1]);
+            diagnostic.message = Some(c[2].to_string());
+            set_location(&mut diagnostic, &c[3], &c[4], Some(&c[5]));
+            diagnostics.push(diagnostic);
+        } else if let Some(c) = TIDY.captures(line) {
+            let mut diagnostic = Diagnostic::new(Kind::Tidy);
+            match TIDY_LOCATION.captures(&c[1]) {
+                Some(location) => {
+                    set_location(&mut diagnostic, &location[1], &location[2], None);
+                    diagnostic.message = Some(location[3].to_string());
+                }
+                None => diagnostic.message = Some(c[1].to_string()),
+            }
+            diagnostics.push(diagnostic);
+        } else if was_pending && !line.is_empty() {
+            // The message of panics is printed on the line following the location.
+            match diagnostics.last_mut() {
+                Some(last) if last.kind == Kind::Panic => last.message = Some(line.to_string()),
+                _ => {}
+            }
+        }
+    }
+
+    diagnostics
+}
+
+fn set_location(diagnostic: &mut Diagnostic, file: &str, line: &str, column: Option<&str>) {
+    diagnostic.file = Some(file.to_string());
+    diagnostic.line = line.parse().ok();
+    diagnostic.column = column.and_then(|c| c.parse().ok());
+}
+
+/// Libtest runs each test in a thread named after it.
+fn panicking_test(thread: &str) -> Option<String> {
+    match thread {
+        "main" | "<unnamed>" | "rustc" => None,
+        test => Some(test.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Sanitized;
+
+    fn parse_lines(lines: &[&str]) -> Vec<Diagnostic> {
+        parse(&lines.iter().map(Sanitized).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_rustc_error() {
+        let diagnostics = parse_lines(&[
+            "error[E0308]: mismatched types",
+            "  --> src/lib.rs:10:5",
+            "   |",
+            "error: aborting due to 1 previous error",
+            "error: could not compile `foo` (lib) due to 1 previous error",
+        ]);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "error[E0308]: mismatched types at src/lib.rs:10:5"
+        );
+        assert_eq!(diagnostics[1].code, None);
+        assert_eq!(diagnostics[1].file, None);
+    }
+
+    #[test]
+    fn test_parse_test_failures() {
+        let diagnostics = parse_lines(&[
+            "---- [ui] tests/ui/foo.rs stdout ----",
+            "---- [ui] tests/ui/bar.rs#edition2021 stdout ----",
+            "---- tests::it_works stdout ----",
+            "thread 'tests::it_works' panicked at src/lib.rs:12:9:",
+            "assertion `left == right` failed",
+            "thread 'main' panicked at 'oops', src/main.rs:1:1",
+            "tidy error: /checkout/src/lib.rs:3: trailing whitespace",
+        ]);
+
+        let rendered = diagnostics
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rendered,
+            vec![
+                "test failed in [ui] tests/ui/foo.rs at tests/ui/foo.rs",
+                "test failed in [ui] tests/ui/bar.rs#edition2021 at tests/ui/bar.rs",
+                "test failed in tests::it_works",
+                "panic in tests::it_works: assertion `left == right` failed at src/lib.rs:12:9",
+                "panic: oops at src/main.rs:1:1",
+                "tidy error: trailing whitespace at /checkout/src/lib.rs:3",
+            ]
+        );
+    }
+}
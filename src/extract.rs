@@ -1,7 +1,12 @@
+use crate::baseline::Baseline;
+use crate::garbage::GarbageFilter;
 use crate::index::{Index, IndexData};
+use crate::sanitize;
+use crate::workflow_commands::{Annotation, WorkflowCommands};
 use aho_corasick::AhoCorasick;
+use std::collections::VecDeque;
 use std::iter;
-use std::mem;
+use std::str::FromStr;
 
 /// Plaintext patterns which, if found in a line, cause all remaining lines to be ignored until the
 /// corresponding pattern (second tuple element) is found in a line.
@@ -46,6 +51,24 @@ lazy_static! {
         .collect();
 }
 
+lazy_static! {
+    /// Plaintext patterns commonly found in lines describing the cause of a failure. Blocks
+    /// containing them are ranked higher by [`extract_ranked`].
+    static ref ANCHORS: AhoCorasick = AhoCorasick::new([
+        "error:",
+        "error[E",
+        "##[error]",
+        "FAILED",
+        "failures:",
+        "panicked at",
+        "assertion failed",
+        "failed to run",
+        "command did not execute successfully",
+        "Build completed unsuccessfully",
+    ])
+    .unwrap();
+}
+
 pub struct Config {
     pub unique_5gram_max_index: u32,
     pub block_merge_distance: usize,
@@ -53,6 +76,24 @@ pub struct Config {
     pub unique_line_min_score: u32,
     pub block_max_lines: usize,
     pub context_lines: usize,
+    /// Relevance added to a block for each line matching one of the anchor patterns.
+    pub anchor_weight: u32,
+    /// Maximum number of anchor hits counted towards the relevance of a single block.
+    pub anchor_max_hits: u32,
+    /// Relevance added to a block ending on the last line of the log, scaled down linearly for
+    /// blocks ending earlier.
+    pub log_end_weight: u32,
+    /// Lines considered garbage (e.g. base64 blobs) neither start nor end a block, and are elided
+    /// when rendered.
+    pub garbage: GarbageFilter,
+}
+
+impl Config {
+    /// Score of the garbage lines, which doesn't trigger a block nor separate blocks, so that a
+    /// blob printed in the middle of an error doesn't split it.
+    fn neutral_score(&self) -> u32 {
+        (self.block_separator_max_score + 1).min(self.unique_line_min_score.saturating_sub(1))
+    }
 }
 
 impl Default for Config {
@@ -64,10 +105,130 @@ impl Default for Config {
             unique_line_min_score: 50,
             block_max_lines: 500,
             context_lines: 4,
+            anchor_weight: 100,
+            anchor_max_hits: 5,
+            log_end_weight: 100,
+            garbage: GarbageFilter::default(),
+        }
+    }
+}
+
+/// Which version of the extracted lines is shown to users.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineOutput {
+    /// The lines as seen by the index, see [`sanitize::clean`].
+    Sanitized,
+    /// The lines as they appear in the log, as rendered by [`sanitize::render_terminal`].
+    Original,
+    /// The original lines, each followed by its sanitized version when they differ.
+    Both,
+}
+
+impl LineOutput {
+    /// Renders the `line`, eliding it if the `garbage` filter considers it as garbage.
+    pub fn render<I: IndexData>(self, line: &I, garbage: &GarbageFilter) -> Vec<u8> {
+        let original = || {
+            garbage
+                .elide(&sanitize::render_terminal(line.original()))
+                .into_owned()
+        };
+        let sanitized = garbage.elide(line.sanitized());
+        match self {
+            LineOutput::Sanitized => sanitized.into_owned(),
+            LineOutput::Original => original(),
+            LineOutput::Both => {
+                let mut rendered = original();
+                if rendered != *sanitized {
+                    rendered.push(b'\n');
+                    rendered.extend_from_slice(&sanitized);
+                }
+                rendered
+            }
         }
     }
 }
 
+impl FromStr for LineOutput {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> crate::Result<Self> {
+        Ok(match input {
+            "sanitized" => LineOutput::Sanitized,
+            "original" => LineOutput::Original,
+            "both" => LineOutput::Both,
+            other => anyhow::bail!("unknown line output: {}", other),
+        })
+    }
+}
+
+/// Result of extracting the potential causes of a failure from a log.
+#[derive(Debug, Serialize)]
+pub struct Extraction {
+    /// The extracted blocks, in the order they appear in the log.
+    pub blocks: Vec<ExtractedBlock>,
+    /// The annotations added by GitHub Actions workflow commands, anywhere in the log.
+    pub annotations: Vec<Annotation>,
+}
+
+/// A block of contiguous lines extracted from the log.
+#[derive(Debug, Serialize)]
+pub struct ExtractedBlock {
+    /// Index of the first line of the block in the extracted lines.
+    pub start: usize,
+    /// Index one past the last line of the block in the extracted lines.
+    pub end: usize,
+    /// Score of each line of the block.
+    pub scores: Vec<u32>,
+    /// The rule which caused the block to be extracted.
+    pub trigger: Trigger,
+}
+
+impl ExtractedBlock {
+    pub fn lines<'i, I: IndexData>(&self, lines: &'i [I]) -> &'i [I] {
+        &lines[self.start..self.end]
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "rule")]
+pub enum Trigger {
+    /// The line at index `line` scored at least `Config::unique_line_min_score`.
+    UniqueLine { line: usize, score: u32 },
+}
+
+/// A block extracted from the log, along with the data used to rank it.
+pub struct Block<T: IndexData> {
+    /// Position of the block in the log, among all the extracted blocks.
+    pub position: usize,
+    /// Index of the first line of the block in the extracted lines.
+    pub start: usize,
+    /// Index one past the last line of the block in the extracted lines.
+    pub end: usize,
+    pub lines: Vec<T>,
+    pub trigger: Trigger,
+    /// Aggregate score used to rank the blocks, higher is more relevant.
+    pub relevance: u64,
+    /// Sum of the scores of all the lines in the block.
+    pub total_score: u64,
+    /// Score of the most unusual line in the block.
+    pub max_score: u32,
+    /// Number of lines in the block matching one of the anchor patterns.
+    pub anchor_hits: u32,
+}
+
+/// A block extracted by a [`StreamingExtractor`], owning its lines.
+pub struct StreamedBlock<T> {
+    /// Index of the first line of the block in the extracted lines.
+    pub start: usize,
+    /// Index one past the last line of the block in the extracted lines.
+    pub end: usize,
+    pub lines: Vec<T>,
+    /// Score of each line of the block.
+    pub scores: Vec<u32>,
+    /// The rule which caused the block to be extracted.
+    pub trigger: Trigger,
+}
+
 pub fn score<I: IndexData>(config: &Config, index: &Index, line: &I) -> u32 {
     index
         .scores(line)
@@ -76,164 +237,494 @@ pub fn score<I: IndexData>(config: &Config, index: &Index, line: &I) -> u32 {
         .sum()
 }
 
-enum State<'a> {
+pub fn extract<'i, I: IndexData + 'i>(
+    config: &Config,
+    index: &Index,
+    lines: &'i [I],
+) -> Vec<Vec<&'i I>> {
+    StreamingExtractor::new(config, index)
+        .blocks(lines)
+        .map(|block| block.lines)
+        .collect()
+}
+
+/// Extracts the blocks of `lines` and returns them sorted by [`Block::relevance`], the most
+/// likely cause of the failure first.
+pub fn extract_ranked<'i, I: IndexData + 'i>(
+    config: &Config,
+    index: &Index,
+    lines: &'i [I],
+) -> Vec<Block<&'i I>> {
+    extract_ranked_from(config, index, lines)
+}
+
+/// Like [`extract_ranked`], but consumes the lines one at a time, only keeping in memory the
+/// lines which are part of a block.
+pub fn extract_ranked_from<T: IndexData, L: IntoIterator<Item = T>>(
+    config: &Config,
+    index: &Index,
+    lines: L,
+) -> Vec<Block<T>> {
+    StreamingExtractor::new(config, index)
+        .blocks(lines)
+        .ranked()
+}
+
+/// Sorts the `blocks` extracted from a log of `total_lines` lines by [`Block::relevance`], the
+/// most likely cause of the failure first.
+pub fn rank<T: IndexData>(
+    config: &Config,
+    blocks: Vec<StreamedBlock<T>>,
+    total_lines: usize,
+) -> Vec<Block<T>> {
+    let mut blocks = blocks
+        .into_iter()
+        .enumerate()
+        .map(|(position, block)| {
+            let anchor_hits = block
+                .lines
+                .iter()
+                .filter(|line| ANCHORS.is_match(line.sanitized()))
+                .count() as u32;
+
+            // Failures are usually reported at the end of the log, so blocks closer to the end
+            // are more likely to contain the actual cause.
+            let log_end_proximity = (u64::from(config.log_end_weight) * block.end as u64
+                / total_lines.max(1) as u64) as u32;
+
+            let total_score = block.scores.iter().map(|&s| u64::from(s)).sum::<u64>();
+            let max_score = block.scores.iter().cloned().max().unwrap_or(0);
+
+            Block {
+                position,
+                start: block.start,
+                end: block.end,
+                relevance: u64::from(max_score)
+                    + total_score / block.scores.len().max(1) as u64
+                    + u64::from(anchor_hits.min(config.anchor_max_hits) * config.anchor_weight)
+                    + u64::from(log_end_proximity),
+                lines: block.lines,
+                trigger: block.trigger,
+                total_score,
+                max_score,
+                anchor_hits,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Ties are broken in favour of the block appearing later in the log.
+    blocks.sort_by(|a, b| {
+        b.relevance
+            .cmp(&a.relevance)
+            .then(b.position.cmp(&a.position))
+    });
+    blocks
+}
+
+impl Extraction {
+    pub fn extract<I: IndexData>(config: &Config, index: &Index, lines: &[I]) -> Extraction {
+        let blocks = StreamingExtractor::new(config, index)
+            .blocks(lines)
+            .map(|block| ExtractedBlock {
+                start: block.start,
+                end: block.end,
+                scores: block.scores,
+                trigger: block.trigger,
+            })
+            .collect();
+
+        Extraction {
+            blocks,
+            annotations: WorkflowCommands::extract(lines).into_annotations(),
+        }
+    }
+}
+
+enum State {
     SearchingSectionStart,
     SearchingOutlier,
     Printing,
-    Ignoring(&'a AhoCorasick),
+    Ignoring(&'static AhoCorasick),
 }
 
-#[derive(Copy, Clone)]
-struct Line<'i, I: IndexData + 'i> {
+struct WindowLine<T> {
+    index: usize,
     score: u32,
-    line: &'i I,
+    line: T,
 }
 
-pub fn extract<'i, I: IndexData + 'i>(
-    config: &Config,
-    index: &Index,
-    lines: &'i [I],
-) -> Vec<Vec<&'i I>> {
-    assert!(config.context_lines < config.block_merge_distance);
+struct ActiveBlock<T> {
+    block: StreamedBlock<T>,
+}
 
-    let lines: Vec<Line<_>> = lines
-        .iter()
-        .map(|line| Line {
-            line,
-            score: score(config, index, line),
-        })
-        .collect();
+/// Extracts blocks from lines pushed one at a time, returning each block as soon as it can't
+/// change anymore.
+///
+/// Only the lines which could still become part of a block are kept in memory, so arbitrarily
+/// long logs can be processed with bounded memory usage.
+pub struct StreamingExtractor<'a, T: IndexData> {
+    config: &'a Config,
+    index: &'a Index,
+    baseline: Option<&'a Baseline>,
+
+    state: State,
+    next_index: usize,
+    section_start: usize,
+    prev_section_end: usize,
+    trailing_context: usize,
+    /// Index one past the last line added to a block.
+    printed_until: usize,
+
+    /// Recent lines which could still be added to a block.
+    window: VecDeque<WindowLine<T>>,
+    /// The block currently being printed.
+    active: Option<ActiveBlock<T>>,
+    /// The last printed block, which can still receive trailing context or be merged.
+    last: Option<ActiveBlock<T>>,
+    finished: VecDeque<StreamedBlock<T>>,
+}
+
+impl<'a, T: IndexData> StreamingExtractor<'a, T> {
+    pub fn new(config: &'a Config, index: &'a Index) -> Self {
+        assert!(config.context_lines < config.block_merge_distance);
+
+        StreamingExtractor {
+            config,
+            index,
+            baseline: None,
+
+            state: State::SearchingSectionStart,
+            next_index: 0,
+            section_start: 0,
+            prev_section_end: 0,
+            trailing_context: 0,
+            printed_until: 0,
+
+            window: VecDeque::new(),
+            active: None,
+            last: None,
+            finished: VecDeque::new(),
+        }
+    }
 
-    let mut i = 0;
-    let mut state = State::SearchingSectionStart;
-    let mut section_start = 0;
-    let mut prev_section_end = 0;
+    /// Suppresses the lines also appearing in the `baseline` log, by considering them as common
+    /// as the block separators.
+    pub fn with_baseline(mut self, baseline: &'a Baseline) -> Self {
+        self.baseline = Some(baseline);
+        self
+    }
 
-    let mut active_block = vec![];
-    let mut blocks = vec![];
+    /// Number of lines pushed so far.
+    pub fn lines_seen(&self) -> usize {
+        self.next_index
+    }
 
-    let mut trailing_context = 0;
+    pub fn push(&mut self, line: T) {
+        let i = self.next_index;
+        self.next_index += 1;
+
+        let score = match self.baseline {
+            Some(baseline) if baseline.contains(&line) => 0,
+            _ if self.config.garbage.is_garbage(line.sanitized()) => self.config.neutral_score(),
+            _ => score(self.config, self.index, &line),
+        };
+        self.window.push_back(WindowLine {
+            index: i,
+            score,
+            line,
+        });
 
-    while i < lines.len() {
-        if let Some(m) = IGNORE_BLOCK_START.find(lines[i].line.sanitized()) {
-            trailing_context = 0;
+        self.process(i, score);
+        self.discard_unneeded(i);
+    }
 
-            if let State::Printing = state {
-                if !active_block.is_empty() {
-                    blocks.push(mem::replace(&mut active_block, vec![]));
-                }
-            }
+    /// Returns the next block which can't change anymore, if any.
+    pub fn pop_block(&mut self) -> Option<StreamedBlock<T>> {
+        self.finished.pop_front()
+    }
 
-            state = State::Ignoring(&IGNORE_BLOCK_END[m.pattern()]);
-            i += 1;
-            continue;
+    /// Marks the end of the log, making all the remaining blocks available through
+    /// [`StreamingExtractor::pop_block`].
+    pub fn finish(&mut self) {
+        if let Some(active) = self.active.take() {
+            self.close(active);
         }
+        if let Some(last) = self.last.take() {
+            self.finished.push_back(last.block);
+        }
+        self.window.clear();
+    }
 
-        match state {
-            State::Ignoring(a) => {
-                if a.find(lines[i].line.sanitized()).is_some() {
-                    state = State::SearchingSectionStart;
-                }
+    /// Pushes all the `lines` and returns an iterator over the extracted blocks.
+    pub fn blocks<L: IntoIterator<Item = T>>(self, lines: L) -> Blocks<'a, T, L::IntoIter> {
+        Blocks {
+            extractor: self,
+            lines: lines.into_iter(),
+            done: false,
+        }
+    }
 
-                i += 1;
-                continue;
-            }
+    fn process(&mut self, i: usize, score: u32) {
+        let current = self.window.back().unwrap().line.sanitized();
+        let ignore_end = match self.state {
+            State::Ignoring(a) => a.find(current).is_some(),
+            _ => false,
+        };
 
-            State::SearchingSectionStart => {
-                if lines[i].score > config.block_separator_max_score {
-                    state = State::SearchingOutlier;
-                    section_start = i;
-                } else {
-                    if trailing_context > 0 {
-                        trailing_context -= 1;
-                        blocks.last_mut().unwrap().push(lines[i].line);
-                        prev_section_end = i;
-                    }
+        if let Some(m) = IGNORE_BLOCK_START.find(current) {
+            self.trailing_context = 0;
 
-                    i += 1;
-                    continue;
+            if let State::Printing = self.state {
+                if let Some(active) = self.active.take() {
+                    self.close(active);
                 }
             }
 
-            State::SearchingOutlier => {
-                if lines[i].score <= config.block_separator_max_score {
-                    if trailing_context > 0 {
-                        trailing_context -= 1;
-                        blocks.last_mut().unwrap().push(lines[i].line);
-                        prev_section_end = i;
+            self.state = State::Ignoring(&IGNORE_BLOCK_END[m.pattern()]);
+            return;
+        }
+
+        loop {
+            match self.state {
+                State::Ignoring(_) => {
+                    if ignore_end {
+                        self.state = State::SearchingSectionStart;
                     }
 
-                    state = State::SearchingSectionStart;
-                    i += 1;
-                    continue;
+                    return;
                 }
 
-                if lines[i].score >= config.unique_line_min_score {
-                    trailing_context = 0;
+                State::SearchingSectionStart => {
+                    if score > self.config.block_separator_max_score {
+                        self.state = State::SearchingOutlier;
+                        self.section_start = i;
+                    } else {
+                        self.push_trailing_context(i);
+                        return;
+                    }
+                }
 
-                    let start_printing;
+                State::SearchingOutlier => {
+                    if score <= self.config.block_separator_max_score {
+                        self.push_trailing_context(i);
+                        self.state = State::SearchingSectionStart;
+                        return;
+                    }
 
-                    if prev_section_end + config.block_merge_distance >= section_start {
-                        if !blocks.is_empty() {
-                            let last_idx = blocks.len() - 1;
-                            active_block = blocks.remove(last_idx);
+                    if score >= self.config.unique_line_min_score {
+                        self.trailing_context = 0;
+
+                        let start_printing;
+                        let mut active;
+
+                        if self.is_mergeable(self.section_start) {
+                            // prev_section_end' line already contained in some block, so start
+                            // from next one. (Except from case from State::Printing and empty
+                            // active_block, idk?). The merged block may also end after
+                            // prev_section_end if it was interrupted by an ignored section, so
+                            // make sure no line is repeated.
+                            start_printing = self.merge_start();
+                            active = self
+                                .last
+                                .take()
+                                .unwrap_or_else(|| ActiveBlock::new(start_printing, i, score));
+                        } else {
+                            // Context lines already printed in the previous block (when it was
+                            // interrupted by an ignored section) are not repeated.
+                            start_printing = self
+                                .section_start
+                                .saturating_sub(self.config.context_lines)
+                                .max(self.printed_until);
+                            active = ActiveBlock::new(start_printing, i, score);
                         }
-                        // prev_section_end' line already contained in some block, so start
-                        // from next one. (Except from case from State::Printing and empty active_block,
-                        // idk?).
-                        start_printing = prev_section_end + 1;
+
+                        for j in start_printing..i {
+                            self.take_line(j, &mut active);
+                        }
+                        self.active = Some(active);
+
+                        self.state = State::Printing;
                     } else {
-                        start_printing = section_start.saturating_sub(config.context_lines);
+                        // No need to update section_start since we'll trigger the `merge` case
+                        // above anyway (prev_section_end >= section_start).
+                        self.push_trailing_context(i);
+                        return;
                     }
+                }
 
-                    for j in start_printing..i {
-                        active_block.push(lines[j].line);
-                    }
+                State::Printing => {
+                    let mut active = self.active.take().unwrap();
+                    self.take_line(i, &mut active);
 
-                    state = State::Printing;
-                } else {
-                    if trailing_context > 0 {
-                        trailing_context -= 1;
-                        blocks.last_mut().unwrap().push(lines[i].line);
-                        prev_section_end = i;
+                    if score <= self.config.block_separator_max_score {
+                        self.close(active);
+                        self.prev_section_end = i;
+                        self.state = State::SearchingSectionStart;
 
-                        // No need to update section_start since we'll trigger the `merge` case above
-                        // anyway (prev_section_end >= section_start).
+                        self.trailing_context = self.config.context_lines;
+                    } else {
+                        self.active = Some(active);
                     }
 
-                    i += 1;
-                    continue;
+                    return;
                 }
             }
+        }
+    }
 
-            State::Printing => {
-                if lines[i].score <= config.block_separator_max_score {
-                    if !active_block.is_empty() {
-                        active_block.push(lines[i].line);
-                        blocks.push(mem::replace(&mut active_block, vec![]));
-                    }
-                    prev_section_end = i;
-                    state = State::SearchingSectionStart;
+    fn push_trailing_context(&mut self, i: usize) {
+        if self.trailing_context > 0 {
+            self.trailing_context -= 1;
+
+            let mut last = self.last.take().unwrap();
+            self.take_line(i, &mut last);
+            self.last = Some(last);
+
+            self.prev_section_end = i;
+        }
+    }
 
-                    trailing_context = config.context_lines;
-                } else {
-                    active_block.push(lines[i].line);
+    /// Moves the line `i` from the window to the `block`.
+    fn take_line(&mut self, i: usize, block: &mut ActiveBlock<T>) {
+        while self.window.front().is_some_and(|line| line.index < i) {
+            self.window.pop_front();
+        }
+
+        self.printed_until = i + 1;
+        if self.window.front().is_some_and(|line| line.index == i) {
+            let line = self.window.pop_front().unwrap();
+            if block.block.lines.len() < self.config.block_max_lines {
+                if block.block.lines.is_empty() {
+                    block.block.start = i;
                 }
+                block.block.lines.push(line.line);
+                block.block.scores.push(line.score);
+                block.block.end = i + 1;
+            }
+        }
+    }
+
+    /// Replaces the last block with the given one, as nothing can change the last block anymore.
+    fn close(&mut self, block: ActiveBlock<T>) {
+        if let Some(last) = self.last.replace(block) {
+            self.finished.push_back(last.block);
+        }
+    }
+
+    /// Whether a section starting at `section_start` would be merged with the last block.
+    fn is_mergeable(&self, section_start: usize) -> bool {
+        self.prev_section_end + self.config.block_merge_distance >= section_start
+    }
+
+    /// The first line to print when a new section is merged with the last block.
+    fn merge_start(&self) -> usize {
+        (self.prev_section_end + 1).max(self.printed_until)
+    }
 
-                i += 1;
-                continue;
+    /// Finalizes the last block and discards the lines in the window once they can't be part of a
+    /// block anymore.
+    fn discard_unneeded(&mut self, i: usize) {
+        let next_section_start = match self.state {
+            State::SearchingOutlier => Some(self.section_start),
+            State::SearchingSectionStart | State::Ignoring(_) => Some(i + 1),
+            // Any merge will happen with the block currently being printed.
+            State::Printing => None,
+        };
+        let mergeable = next_section_start.is_some_and(|start| self.is_mergeable(start));
+
+        if self.trailing_context == 0 && !mergeable {
+            if let Some(last) = self.last.take() {
+                self.finished.push_back(last.block);
             }
         }
+
+        let context_start = |start: usize| start.saturating_sub(self.config.context_lines);
+        let keep_from = match (&self.state, next_section_start) {
+            (_, None) => i + 1,
+            // The current section can only be merged with the last block, so the block would
+            // resume right after it.
+            (State::SearchingOutlier, Some(_)) if mergeable => self.merge_start(),
+            (_, Some(start)) if mergeable => self.merge_start().min(context_start(start)),
+            (_, Some(start)) => context_start(start),
+        };
+        while self
+            .window
+            .front()
+            .is_some_and(|line| line.index < keep_from)
+        {
+            self.window.pop_front();
+        }
+
+        // Blocks only keep their first lines (see `Config::block_max_lines`), but the most recent
+        // lines might still be needed as context for the next block.
+        let max_window = self
+            .config
+            .block_max_lines
+            .saturating_add(self.config.block_merge_distance);
+        if self.window.len() > max_window {
+            self.window.remove(self.config.block_max_lines);
+        }
     }
+}
 
-    if !active_block.is_empty() {
-        blocks.push(active_block);
+impl<T> ActiveBlock<T> {
+    fn new(start: usize, trigger_line: usize, trigger_score: u32) -> Self {
+        ActiveBlock {
+            block: StreamedBlock {
+                start,
+                end: start,
+                lines: vec![],
+                scores: vec![],
+                trigger: Trigger::UniqueLine {
+                    line: trigger_line,
+                    score: trigger_score,
+                },
+            },
+        }
     }
+}
 
-    blocks.retain(|block| !block.is_empty());
-    blocks
-        .iter_mut()
-        .for_each(|block| block.truncate(config.block_max_lines));
+/// Iterator over the blocks extracted from a sequence of lines, see
+/// [`StreamingExtractor::blocks`].
+pub struct Blocks<'a, T: IndexData, L: Iterator<Item = T>> {
+    extractor: StreamingExtractor<'a, T>,
+    lines: L,
+    done: bool,
+}
 
-    blocks
+impl<T: IndexData, L: Iterator<Item = T>> Blocks<'_, T, L> {
+    /// Number of lines consumed so far.
+    pub fn lines_seen(&self) -> usize {
+        self.extractor.lines_seen()
+    }
+
+    /// Consumes all the remaining lines and returns the blocks sorted by [`Block::relevance`], see
+    /// [`rank`].
+    pub fn ranked(mut self) -> Vec<Block<T>> {
+        let blocks = self.by_ref().collect();
+        rank(self.extractor.config, blocks, self.lines_seen())
+    }
+}
+
+impl<T: IndexData, L: Iterator<Item = T>> Iterator for Blocks<'_, T, L> {
+    type Item = StreamedBlock<T>;
+
+    fn next(&mut self) -> Option<StreamedBlock<T>> {
+        loop {
+            if let Some(block) = self.extractor.pop_block() {
+                return Some(block);
+            }
+            if self.done {
+                return None;
+            }
+
+            match self.lines.next() {
+                Some(line) => self.extractor.push(line),
+                None => {
+                    self.extractor.finish();
+                    self.done = true;
+                }
+            }
+        }
+    }
 }
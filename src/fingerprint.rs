@@ -0,0 +1,211 @@
+use crate::index::IndexData;
+use crate::normalize::Normalizer;
+use crate::Result;
+use atomicwrites::{AtomicFile, OverwriteBehavior};
+use regex::bytes::Regex;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long failures are remembered by default to detect recurring ones.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+lazy_static! {
+    /// Numbers, e.g. the counts of errors or the line numbers, which change between occurrences of
+    /// the same failure but are meaningful when scoring the lines.
+    static ref NUMBER: Regex = Regex::new("[0-9]+").unwrap();
+}
+
+/// Identifies a failure across builds, computed from its lines with the parts which change
+/// between builds removed, see [`normalize`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Fingerprint(pub String);
+
+impl Fingerprint {
+    pub fn new<'a, I: IndexData + 'a>(
+        normalizer: &Normalizer,
+        lines: impl IntoIterator<Item = &'a I>,
+    ) -> Self {
+        let mut hasher = Sha1::new();
+        for line in lines {
+            hasher.update(normalize(normalizer, line.sanitized()));
+            hasher.update(b"\n");
+        }
+        Fingerprint(hex::encode(hasher.finalize()))
+    }
+}
+
+/// Normalizes the `line` with `normalizer`, usually the one of the index, and also replaces all
+/// the numbers with placeholders.
+pub fn normalize(normalizer: &Normalizer, line: &[u8]) -> Vec<u8> {
+    NUMBER
+        .replace_all(&normalizer.normalize(line), &b"<n>"[..])
+        .into_owned()
+}
+
+/// A build in which a failure was seen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Occurrence {
+    pub repo: String,
+    pub pr: u32,
+    /// Seconds since the UNIX epoch.
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    /// A normalized line of the failure, to help recognizing it.
+    pub sample: String,
+    /// The occurrences of the failure, oldest first.
+    pub occurrences: Vec<Occurrence>,
+}
+
+/// Recently seen failures, persisted as JSON in a local file.
+pub struct FingerprintStore {
+    path: PathBuf,
+    /// How long occurrences are kept, in seconds.
+    max_age: u64,
+    entries: HashMap<Fingerprint, Entry>,
+}
+
+impl FingerprintStore {
+    /// Loads the store from `path`, or creates an empty one if the file doesn't exist yet.
+    pub fn load(path: &Path, max_age: u64) -> Result<Self> {
+        let entries = match File::open(path) {
+            Ok(file) => serde_json::from_reader::<_, Vec<(Fingerprint, Entry)>>(file)?
+                .into_iter()
+                .collect(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(FingerprintStore {
+            path: path.into(),
+            max_age,
+            entries,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let entries = self.entries.iter().collect::<Vec<_>>();
+        let file = AtomicFile::new(&self.path, OverwriteBehavior::AllowOverwrite);
+        file.write(|inner| serde_json::to_writer(inner, &entries))
+            .map_err(|e| anyhow::anyhow!("failed to save the fingerprints: {}", e))?;
+        Ok(())
+    }
+
+    /// Returns the recent occurrences of the failure, oldest first.
+    pub fn occurrences(&self, fingerprint: &Fingerprint) -> &[Occurrence] {
+        self.entries
+            .get(fingerprint)
+            .map_or(&[], |entry| &entry.occurrences)
+    }
+
+    /// Records a new occurrence of the failure, and forgets about the old occurrences of all the
+    /// failures. The `sample` is expected to be already [`normalize`]d.
+    pub fn record(&mut self, fingerprint: Fingerprint, sample: &[u8], occurrence: Occurrence) {
+        let oldest = occurrence.timestamp.saturating_sub(self.max_age);
+
+        self.entries
+            .entry(fingerprint)
+            .or_insert_with(|| Entry {
+                sample: String::from_utf8_lossy(sample).into_owned(),
+                occurrences: vec![],
+            })
+            .occurrences
+            .push(occurrence);
+
+        for entry in self.entries.values_mut() {
+            entry.occurrences.retain(|o| o.timestamp >= oldest);
+        }
+        self.entries
+            .retain(|_, entry| !entry.occurrences.is_empty());
+    }
+
+    /// Returns the `count` failures seen the most often, the most frequent first.
+    pub fn top(&self, count: usize) -> Vec<(&Fingerprint, &Entry)> {
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| {
+            b.1.occurrences
+                .len()
+                .cmp(&a.1.occurrences.len())
+                .then_with(|| a.0 .0.cmp(&b.0 .0))
+        });
+        entries.truncate(count);
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Sanitized;
+
+    #[test]
+    fn test_normalize() {
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            normalize(
+                &normalizer,
+                b"error: could not compile `foo` (lib) due to 3 previous errors"
+            ),
+            b"error: could not compile `foo` (lib) due to <n> previous errors"
+        );
+        // The rules of the normalizer are applied before replacing the numbers.
+        assert_eq!(
+            normalize(
+                &normalizer,
+                b"thread 'main' panicked at /tmp/foo/lib.rs:10:5: oops 3f2a9b1c in 1.5s"
+            ),
+            b"thread 'main' panicked at <tmp>:<n>:<n>: oops <hash> in <duration>"
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_numbers() {
+        let first = [Sanitized("test result: FAILED. 10 passed; 1 failed")];
+        let second = [Sanitized("test result: FAILED. 12 passed; 1 failed")];
+        let other = [Sanitized("error[E0308]: mismatched types")];
+
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            Fingerprint::new(&normalizer, &first),
+            Fingerprint::new(&normalizer, &second)
+        );
+        assert_ne!(
+            Fingerprint::new(&normalizer, &first),
+            Fingerprint::new(&normalizer, &other)
+        );
+    }
+
+    #[test]
+    fn test_store_forgets_old_occurrences() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fingerprints.json");
+        let mut store = FingerprintStore::load(&path, 100).unwrap();
+        let fingerprint = Fingerprint("abc".into());
+        let occurrence = |pr, timestamp| Occurrence {
+            repo: "rust-lang/rust".into(),
+            pr,
+            timestamp,
+        };
+
+        store.record(fingerprint.clone(), b"oops", occurrence(1, 1000));
+        store.record(fingerprint.clone(), b"oops", occurrence(2, 1050));
+        store.save().unwrap();
+
+        let mut store = FingerprintStore::load(&path, 100).unwrap();
+        assert_eq!(store.occurrences(&fingerprint).len(), 2);
+
+        store.record(fingerprint.clone(), b"oops", occurrence(3, 1120));
+        let prs = store
+            .occurrences(&fingerprint)
+            .iter()
+            .map(|o| o.pr)
+            .collect::<Vec<_>>();
+        assert_eq!(prs, vec![2, 3]);
+        assert_eq!(store.top(10)[0].1.sample, "oops");
+    }
+}
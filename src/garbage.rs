@@ -0,0 +1,171 @@
+use std::borrow::Cow;
+
+/// Detects the lines which are not meant to be read by humans, like base64 blobs, minified data
+/// or binary dumps. They look unusual to the index, so they would otherwise be extracted and fill
+/// the reports.
+pub struct GarbageFilter {
+    /// Lines longer than this many bytes are considered garbage.
+    pub max_line_length: usize,
+    /// Words (runs of non-whitespace) shorter than this are not checked for entropy, as short
+    /// identifiers and hashes are common in legitimate lines.
+    pub min_word_length: usize,
+    /// Words with an entropy of at least this many bits per byte are considered encoded data.
+    /// English text is around 4, base64 close to 6.
+    pub max_word_entropy: f64,
+    /// Lines with at least this proportion of bytes which are not valid UTF-8 are considered
+    /// binary data.
+    pub max_invalid_ratio: f64,
+    /// How many bytes of the garbage lines are kept when rendering them.
+    pub kept_bytes: usize,
+}
+
+impl Default for GarbageFilter {
+    fn default() -> Self {
+        GarbageFilter {
+            max_line_length: 4096,
+            min_word_length: 64,
+            max_word_entropy: 5.0,
+            max_invalid_ratio: 0.1,
+            kept_bytes: 100,
+        }
+    }
+}
+
+impl GarbageFilter {
+    /// A filter never considering lines as garbage.
+    pub fn disabled() -> Self {
+        GarbageFilter {
+            max_line_length: usize::MAX,
+            min_word_length: usize::MAX,
+            max_word_entropy: f64::INFINITY,
+            max_invalid_ratio: f64::INFINITY,
+            kept_bytes: usize::MAX,
+        }
+    }
+
+    pub fn is_garbage(&self, line: &[u8]) -> bool {
+        if line.len() > self.max_line_length {
+            return true;
+        }
+
+        let invalid = line
+            .utf8_chunks()
+            .map(|chunk| chunk.invalid().len())
+            .sum::<usize>();
+        if invalid > 0 && invalid as f64 >= line.len() as f64 * self.max_invalid_ratio {
+            return true;
+        }
+
+        line.split(|b| b.is_ascii_whitespace())
+            .filter(|word| word.len() >= self.min_word_length)
+            .any(|word| !is_identifier(word) && entropy(word) >= self.max_word_entropy)
+    }
+
+    /// Returns the `line` truncated and followed by a marker if it's garbage, or unchanged
+    /// otherwise.
+    pub fn elide<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        if !self.is_garbage(line) {
+            return Cow::Borrowed(line);
+        }
+
+        // Avoid cutting a character in half.
+        let mut kept = self.kept_bytes.min(line.len());
+        while kept > 0 && kept < line.len() && (line[kept] & 0b1100_0000) == 0b1000_0000 {
+            kept -= 1;
+        }
+
+        let mut elided = line[..kept].to_vec();
+        elided.extend_from_slice(
+            format!(
+                " [... {} bytes of binary or encoded data elided ...]",
+                line.len() - kept
+            )
+            .as_bytes(),
+        );
+        Cow::Owned(elided)
+    }
+}
+
+/// Whether the `word`, ignoring the punctuation around it, looks like an identifier. Mangled
+/// symbols, e.g. in linker errors, can be as random as encoded data, which on the other hand
+/// almost always contains other characters (`+`, `/` or `=` in base64).
+fn is_identifier(word: &[u8]) -> bool {
+    let is_identifier_byte = |b: &u8| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'$' | b'.');
+    let Some(start) = word.iter().position(is_identifier_byte) else {
+        return false;
+    };
+    let end = word.iter().rposition(is_identifier_byte).unwrap() + 1;
+    word[start..end].iter().all(is_identifier_byte)
+}
+
+/// Shannon entropy of the bytes of `data`, in bits per byte.
+fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE64: &str = "H4sIAAAAAAAAA+1Ze3PbNhLP5/gUGN7MOZ2xJL5EStNJrxhbiZPYsU+Wm7S2x0ORkMWYIlWQsq/x+LvfAnxI+kOyJ1p0R1w9";
+
+    #[test]
+    fn test_is_garbage() {
+        let filter = GarbageFilter::default();
+
+        assert!(filter.is_garbage(BASE64.as_bytes()));
+        assert!(filter.is_garbage(format!("data: {}", BASE64).as_bytes()));
+        assert!(filter.is_garbage(b"\x7fELF\x02\x01\x01\x00\xff\xfe\xfd\x00\x00\x00\x00\x00"));
+        assert!(filter.is_garbage("a".repeat(5000).as_bytes()));
+
+        assert!(!filter.is_garbage(b"error[E0308]: mismatched types"));
+        assert!(!filter.is_garbage(
+            b"  --> /checkout/compiler/rustc_codegen_ssa/src/back/link/some_very_long_module_name.rs:10:5"
+        ));
+        assert!(!filter.is_garbage(
+            b"commit 3f2a9b1c0d3f2a9b1c0d3f2a9b1c0d3f2a9b1c0d3f2a9b1c0d3f2a9b1c0d3f2a"
+        ));
+    }
+
+    #[test]
+    fn test_mangled_symbols_are_not_garbage() {
+        let filter = GarbageFilter::default();
+        let symbol = "_RNvMs0_NtCs4fqI2P2rA04_13rustc_codegen_ssa4back6linkerNtB5_9GccLinkerNtCsdWb7mYzL3hV_5linux11link_dylib";
+        assert!(entropy(symbol.as_bytes()) >= filter.max_word_entropy);
+
+        assert!(!filter.is_garbage(format!("undefined reference to `{symbol}'").as_bytes()));
+        assert!(!filter.is_garbage(format!("  = note: {symbol}: error").as_bytes()));
+        assert!(filter.is_garbage(format!("`{BASE64}'").as_bytes()));
+    }
+
+    #[test]
+    fn test_elide() {
+        let filter = GarbageFilter {
+            kept_bytes: 10,
+            ..GarbageFilter::default()
+        };
+
+        assert_eq!(
+            &*filter.elide(format!("data: {}", BASE64).as_bytes()),
+            format!(
+                "data: H4sI [... {} bytes of binary or encoded data elided ...]",
+                BASE64.len() - 4
+            )
+            .as_bytes()
+        );
+        assert!(matches!(filter.elide(b"error: oops"), Cow::Borrowed(_)));
+    }
+}
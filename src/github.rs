@@ -11,7 +11,7 @@ const TIMEOUT_SECS: u64 = 15;
 static ACCEPT_VERSION: &str = "application/vnd.github.v3+json";
 static API_BASE: &str = "https://api.github.com";
 
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum BuildStatus {
     Queued,
@@ -19,7 +19,7 @@ pub enum BuildStatus {
     Completed,
 }
 
-#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
 pub enum BuildConclusion {
     Success,
@@ -95,6 +95,62 @@ struct Comment<'a> {
     body: &'a str,
 }
 
+#[derive(Serialize)]
+struct Labels<'a> {
+    labels: &'a [&'a str],
+}
+
+/// The GitHub API only accepts this many annotations per request.
+const MAX_ANNOTATIONS_PER_REQUEST: usize = 50;
+
+#[derive(Serialize)]
+struct NewCheckRun<'a> {
+    name: &'a str,
+    head_sha: &'a str,
+    status: BuildStatus,
+    conclusion: BuildConclusion,
+    output: &'a CheckRunOutput,
+}
+
+#[derive(Deserialize)]
+struct CreatedCheckRun {
+    id: u64,
+}
+
+/// What a check run shows in the Checks tab of the PR.
+#[derive(Serialize, Debug)]
+pub struct CheckRunOutput {
+    pub title: String,
+    /// Markdown, up to 65535 characters.
+    pub summary: String,
+    pub annotations: Vec<CheckAnnotation>,
+}
+
+/// A message attached to a line of a file of the repository.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct CheckAnnotation {
+    /// Path of the file, relative to the root of the repository.
+    pub path: String,
+    pub start_line: u32,
+    pub end_line: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_column: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_column: Option<u32>,
+    pub annotation_level: AnnotationLevel,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+#[derive(Serialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnotationLevel {
+    Notice,
+    Warning,
+    Failure,
+}
+
 #[derive(Deserialize)]
 pub struct CheckRunEvent {
     pub check_run: CheckRun,
@@ -154,6 +210,16 @@ pub struct PullRequestEvent {
     pub repository: Repository,
 }
 
+/// A comment on a pull request, as returned by the GraphQL API.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrComment {
+    /// The GraphQL node ID of the comment.
+    pub id: String,
+    pub body: String,
+    pub is_minimized: bool,
+}
+
 #[derive(Deserialize)]
 struct GraphResponse<T> {
     data: T,
@@ -243,13 +309,88 @@ impl Client {
         Ok(())
     }
 
+    pub fn add_label(&self, repo: &str, issue_id: u32, label: &str) -> Result<()> {
+        let resp = self
+            .internal
+            .post(format!("{}/repos/{}/issues/{}/labels", API_BASE, repo, issue_id).as_str())
+            .json(&Labels { labels: &[label] })
+            .send()?;
+        if !resp.status().is_success() {
+            bail!("Adding label failed: {:?}", resp);
+        }
+
+        Ok(())
+    }
+
+    /// Creates a completed check run on the `head_sha` commit. This requires the token to belong
+    /// to a GitHub App.
+    pub fn create_check_run(
+        &self,
+        repo: &str,
+        head_sha: &str,
+        name: &str,
+        conclusion: BuildConclusion,
+        output: &CheckRunOutput,
+    ) -> Result<()> {
+        // The remaining annotations are added by updating the check run.
+        let mut annotations = output.annotations.chunks(MAX_ANNOTATIONS_PER_REQUEST);
+        let with_annotations = |annotations: &[CheckAnnotation]| CheckRunOutput {
+            title: output.title.clone(),
+            summary: output.summary.clone(),
+            annotations: annotations.to_vec(),
+        };
+        let first = with_annotations(annotations.next().unwrap_or_default());
+
+        let resp = self
+            .internal
+            .post(format!("{}/repos/{}/check-runs", API_BASE, repo).as_str())
+            .json(&NewCheckRun {
+                name,
+                head_sha,
+                status: BuildStatus::Completed,
+                conclusion,
+                output: &first,
+            })
+            .send()?;
+        if !resp.status().is_success() {
+            bail!("Creating check run failed: {:?}", resp);
+        }
+        let created: CreatedCheckRun = resp.json()?;
+
+        for chunk in annotations {
+            let resp = self
+                .internal
+                .patch(format!("{}/repos/{}/check-runs/{}", API_BASE, repo, created.id).as_str())
+                .json(&serde_json::json!({ "output": with_annotations(chunk) }))
+                .send()?;
+            if !resp.status().is_success() {
+                bail!("Adding check run annotations failed: {:?}", resp);
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn hide_own_comments(&self, repo: &str, pull_request_id: u32) -> Result<()> {
+        debug!("started hiding comments in {}#{}", repo, pull_request_id);
+
+        for comment in self.query_own_comments(repo, pull_request_id)? {
+            if !comment.is_minimized {
+                self.hide_comment(&comment.id, "OUTDATED")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The comments posted by the authenticated user on a pull request, oldest first.
+    pub fn query_own_comments(&self, repo: &str, pull_request_id: u32) -> Result<Vec<PrComment>> {
         const QUERY: &str = "query($owner: String!, $repo: String!, $pr: Int!, $cursor: String) {
                 repository(owner: $owner, name: $repo) {
                     pullRequest(number: $pr) {
                         comments(first: 100, after: $cursor) {
                             nodes {
                                 id
+                                body
                                 isMinimized
                                 viewerDidAuthor
                             }
@@ -283,13 +424,11 @@ impl Client {
         #[derive(Debug, Deserialize)]
         #[serde(rename_all = "camelCase")]
         struct ResponseComment {
-            id: String,
-            is_minimized: bool,
+            #[serde(flatten)]
+            comment: PrComment,
             viewer_did_author: bool,
         }
 
-        debug!("started hiding comments in {}#{}", repo, pull_request_id);
-
         let (owner, repo) = if let Some(mid) = repo.find('/') {
             let split = repo.split_at(mid);
             (split.0, split.1.trim_start_matches('/'))
@@ -300,7 +439,7 @@ impl Client {
         let mut comments = Vec::new();
         let mut cursor = None;
         loop {
-            let mut resp: Response = self.graphql(
+            let resp: Response = self.graphql(
                 QUERY,
                 serde_json::json!({
                     "owner": owner,
@@ -310,18 +449,43 @@ impl Client {
                 }),
             )?;
             cursor = resp.repository.pull_request.comments.page_info.end_cursor;
-            comments.append(&mut resp.repository.pull_request.comments.nodes);
+            comments.extend(
+                resp.repository
+                    .pull_request
+                    .comments
+                    .nodes
+                    .into_iter()
+                    .filter(|node| node.viewer_did_author)
+                    .map(|node| node.comment),
+            );
 
             if cursor.is_none() {
                 break;
             }
         }
+        Ok(comments)
+    }
 
-        for comment in &comments {
-            if comment.viewer_did_author && !comment.is_minimized {
-                self.hide_comment(&comment.id, "OUTDATED")?;
+    /// Replaces the body of the comment with the given GraphQL node ID.
+    pub fn edit_comment(&self, node_id: &str, body: &str) -> Result<()> {
+        #[derive(Deserialize)]
+        struct UpdateData {}
+
+        const UPDATE: &str = "mutation($node_id: ID!, $body: String!) {
+            updateIssueComment(input: {id: $node_id, body: $body}) {
+                __typename
             }
-        }
+        }";
+
+        trace!("editing comment {}", node_id);
+
+        self.graphql::<Option<UpdateData>, _>(
+            UPDATE,
+            serde_json::json!({
+                "node_id": node_id,
+                "body": body,
+            }),
+        )?;
         Ok(())
     }
 
@@ -360,7 +524,7 @@ impl Client {
 
         let response: GraphResponse<T> = self
             .internal
-            .post(&format!("{}/graphql", API_BASE))
+            .post(format!("{}/graphql", API_BASE))
             .json(&GraphPayload { query, variables })
             .send()?
             .error_for_status()?
@@ -1,4 +1,6 @@
 use super::Result;
+use crate::normalize::{Normalizer, Rule};
+use std::io::Read;
 use std::slice;
 
 mod storage;
@@ -8,6 +10,21 @@ pub use self::storage::IndexStorage;
 
 pub trait IndexData {
     fn sanitized(&self) -> &[u8];
+
+    /// The line as it appeared in the log, before being sanitized.
+    fn original(&self) -> &[u8] {
+        self.sanitized()
+    }
+}
+
+impl<T: IndexData + ?Sized> IndexData for &T {
+    fn sanitized(&self) -> &[u8] {
+        (**self).sanitized()
+    }
+
+    fn original(&self) -> &[u8] {
+        (**self).original()
+    }
 }
 
 pub struct Sanitized<T: AsRef<[u8]>>(pub T);
@@ -18,14 +35,39 @@ impl<T: AsRef<[u8]>> IndexData for Sanitized<T> {
     }
 }
 
-#[derive(Default, Serialize, Deserialize)]
+/// Starts the serialized indexes, followed by a [`Header`] and the table. Indexes serialized before
+/// the header was introduced only contain the table.
+const MAGIC: &[u8; 8] = b"RLAINDEX";
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    normalization: Vec<Rule>,
+}
+
+#[derive(Default, Clone)]
 pub struct Index {
+    /// How the lines are normalized before being learned or scored.
+    normalizer: Normalizer,
     internal: fnv::FnvHashMap<u32, u32>,
 }
 
 impl Index {
+    /// Creates an empty index normalizing the lines with `normalizer`.
+    pub fn new(normalizer: Normalizer) -> Self {
+        Index {
+            normalizer,
+            internal: Default::default(),
+        }
+    }
+
+    pub fn normalizer(&self) -> &Normalizer {
+        &self.normalizer
+    }
+
     pub fn learn<I: IndexData>(&mut self, data: &I, multiplier: u32) {
-        let encoded = encode(data);
+        let encoded = self.encode(data);
 
         for id in IdIter::new(&encoded) {
             let val = self.internal.entry(id).or_insert(0);
@@ -33,8 +75,21 @@ impl Index {
         }
     }
 
+    /// Adds the lines learned by `other`, which must normalize the lines the same way. Allows
+    /// learning from a log without holding a lock on a shared index.
+    pub fn merge(&mut self, other: Index) -> Result<()> {
+        if self.normalizer != other.normalizer {
+            anyhow::bail!("cannot merge indexes built with different normalization rules");
+        }
+        for (id, count) in other.internal {
+            let val = self.internal.entry(id).or_insert(0);
+            *val = val.saturating_add(count);
+        }
+        Ok(())
+    }
+
     pub fn scores<I: IndexData>(&self, data: &I) -> std::vec::IntoIter<u32> {
-        let encoded = encode(data);
+        let encoded = self.encode(data);
 
         IdIter::new(&encoded)
             .map(|id| self.internal.get(&id).cloned().unwrap_or(0))
@@ -49,43 +104,90 @@ impl Index {
         Ok(())
     }
 
+    /// Loads the index, which normalizes the lines the same way as when it was built.
     pub fn load(storage: &IndexStorage) -> Result<Index> {
-        Index::load_or_create_internal(storage, false)
+        Index::load_or_create_internal(storage, None)
+    }
+
+    /// Loads the index with the normalization rules it was built with, or creates an empty one
+    /// with the default rules if it doesn't exist yet.
+    pub fn load_or_default(storage: &IndexStorage) -> Result<Index> {
+        Index::load_or_create_internal(storage, Some(Normalizer::default()))
     }
 
-    pub fn load_or_create(storage: &IndexStorage) -> Result<Index> {
-        Index::load_or_create_internal(storage, true)
+    /// Loads the index, or creates an empty one normalizing the lines with `normalizer` if it
+    /// doesn't exist yet. Existing indexes must have been built with the same normalization rules.
+    pub fn load_or_create(storage: &IndexStorage, normalizer: Normalizer) -> Result<Index> {
+        let index = Index::load_or_create_internal(storage, Some(normalizer.clone()))?;
+        if index.normalizer != normalizer {
+            anyhow::bail!(
+                "the index at '{storage}' was built with different normalization rules, \
+                 learning from scratch with the new rules is required"
+            );
+        }
+        Ok(index)
     }
 
-    fn load_or_create_internal(storage: &IndexStorage, create: bool) -> Result<Index> {
+    fn load_or_create_internal(
+        storage: &IndexStorage,
+        create: Option<Normalizer>,
+    ) -> Result<Index> {
         info!("Loading index...");
         let index = if let Some(index) = storage.read()? {
             index
+        } else if let Some(normalizer) = create {
+            info!("Index missing, initializing new index...");
+            Index::new(normalizer)
         } else {
-            if create {
-                info!("Index missing, initializing new index...");
-                Index::default()
-            } else {
-                anyhow::bail!("missing index, aborting");
-            }
+            anyhow::bail!("missing index, aborting");
         };
 
-        info!("Index ready ({} keys).", index.internal.len());
+        info!(
+            "Index ready ({} keys, {} normalization rules).",
+            index.internal.len(),
+            index.normalizer.rules().len()
+        );
 
         Ok(index)
     }
 
-    fn deserialize(reader: &mut dyn std::io::Read) -> Result<Self> {
-        Ok(bincode::deserialize_from(reader)?)
+    fn deserialize(reader: &mut dyn Read) -> Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Ok(Index {
+                normalizer: Normalizer::none(),
+                internal: bincode::deserialize_from(magic.chain(reader))?,
+            });
+        }
+
+        let header: Header = bincode::deserialize_from(&mut *reader)?;
+        if header.version != VERSION {
+            anyhow::bail!("unsupported index version: {}", header.version);
+        }
+        Ok(Index {
+            normalizer: Normalizer::new(header.normalization)?,
+            internal: bincode::deserialize_from(reader)?,
+        })
     }
 
     fn serialize(
         &self,
         writer: &mut dyn std::io::Write,
     ) -> std::result::Result<(), bincode::Error> {
-        bincode::serialize_into(writer, self)?;
+        writer.write_all(MAGIC)?;
+        let header = Header {
+            version: VERSION,
+            normalization: self.normalizer.rules().to_vec(),
+        };
+        bincode::serialize_into(&mut *writer, &header)?;
+        bincode::serialize_into(writer, &self.internal)?;
         Ok(())
     }
+
+    fn encode<I: IndexData>(&self, data: &I) -> Vec<u8> {
+        encode(&Sanitized(self.normalizer.normalize(data.sanitized())))
+    }
 }
 
 pub fn encode<I: IndexData>(data: &I) -> Vec<u8> {
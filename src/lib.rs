@@ -23,12 +23,21 @@ extern crate sha1;
 #[macro_use]
 extern crate tracing;
 
+pub mod baseline;
+pub mod budget;
 pub mod ci;
+pub mod classify;
+pub mod diagnostics;
 pub mod extract;
+pub mod fingerprint;
+pub mod garbage;
 pub mod github;
 pub mod index;
 pub mod log_variables;
+pub mod normalize;
 pub mod sanitize;
+pub mod test_summary;
+pub mod workflow_commands;
 
 pub use self::index::Index;
 
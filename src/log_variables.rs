@@ -1,60 +1,100 @@
+use std::collections::HashMap;
+
 const START_DELIMITER: u8 = b'[';
 const END_DELIMITER: u8 = b']';
 const SEPARATOR: u8 = b'=';
 
-const JOB_NAME_VARIABLE: &str = "CI_JOB_NAME";
-const PR_NUMBER_VARIABLE: &str = "CI_PR_NUMBER";
+pub const JOB_NAME_VARIABLE: &str = "CI_JOB_NAME";
+pub const PR_NUMBER_VARIABLE: &str = "CI_PR_NUMBER";
 /// URL pointing to a documentation page about the job.
 /// Added in https://github.com/rust-lang/rust/pull/136911.
-const JOB_DOC_URL: &str = "CI_JOB_DOC_URL";
+pub const JOB_DOC_URL: &str = "CI_JOB_DOC_URL";
+
+/// Variables used by the analyzer, always recorded.
+const WELL_KNOWN_VARIABLES: &[&str] = &[JOB_NAME_VARIABLE, PR_NUMBER_VARIABLE, JOB_DOC_URL];
+
+/// Maximum number of variables remembered, so lines looking like variables can't fill the memory.
+/// The well-known and wanted variables are recorded even once it's reached.
+const MAX_VARIABLES: usize = 256;
 
-pub struct LogVariables<'a> {
-    pub job_name: Option<&'a str>,
-    pub pr_number: Option<&'a str>,
-    pub doc_url: Option<&'a str>,
+/// The variables defined by the CI scripts in the log, with lines of the form `[NAME=value]`.
+#[derive(Default)]
+pub struct LogVariables {
+    variables: HashMap<String, String>,
+    /// Variables recorded regardless of `MAX_VARIABLES`, besides the well-known ones.
+    wanted: Vec<String>,
 }
 
-impl<'a> LogVariables<'a> {
-    pub fn extract<I: crate::index::IndexData>(lines: &'a [I]) -> Self {
-        let mut result = LogVariables {
-            job_name: None,
-            pr_number: None,
-            doc_url: None,
-        };
+impl LogVariables {
+    /// Records the `wanted` variables in addition to the well-known ones, even in logs defining
+    /// lots of variables.
+    pub fn with_wanted(wanted: &[String]) -> Self {
+        LogVariables {
+            variables: HashMap::new(),
+            wanted: wanted.to_vec(),
+        }
+    }
+
+    pub fn extract<I: crate::index::IndexData>(lines: &[I]) -> Self {
+        let mut result = LogVariables::default();
 
         for line in lines {
-            let sanitized = line.sanitized();
-
-            if result.job_name.is_none() {
-                result.job_name = extract_variable(sanitized, JOB_NAME_VARIABLE);
-            }
-            if result.pr_number.is_none() {
-                result.pr_number = extract_variable(sanitized, PR_NUMBER_VARIABLE);
-            }
-            if result.doc_url.is_none() {
-                result.doc_url = extract_variable(sanitized, JOB_DOC_URL);
-            }
-
-            // Early exit if everything was found
-            if result.job_name.is_some() && result.pr_number.is_some() && result.doc_url.is_some() {
-                break;
-            }
+            result.observe(line);
         }
 
         result
     }
+
+    /// Records the variable defined by `line`, for logs which are read incrementally. Variables
+    /// already found are not overridden.
+    pub fn observe<I: crate::index::IndexData>(&mut self, line: &I) {
+        let Some((name, value)) = parse_variable(line.sanitized()) else {
+            return;
+        };
+        if self.variables.len() >= MAX_VARIABLES
+            && !WELL_KNOWN_VARIABLES.contains(&name)
+            && !self.wanted.iter().any(|wanted| wanted == name)
+        {
+            return;
+        }
+        self.variables
+            .entry(name.to_string())
+            .or_insert_with(|| value.to_string());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    pub fn job_name(&self) -> Option<&str> {
+        self.get(JOB_NAME_VARIABLE)
+    }
+
+    pub fn pr_number(&self) -> Option<&str> {
+        self.get(PR_NUMBER_VARIABLE)
+    }
+
+    pub fn doc_url(&self) -> Option<&str> {
+        self.get(JOB_DOC_URL)
+    }
 }
 
-fn extract_variable<'a>(line: &'a [u8], name: &str) -> Option<&'a str> {
+/// Parses a `[NAME=value]` line, the name only containing ASCII alphanumeric characters and
+/// underscores.
+fn parse_variable(line: &[u8]) -> Option<(&str, &str)> {
     if line.first() != Some(&START_DELIMITER) || line.last() != Some(&END_DELIMITER) {
         return None;
     }
 
     let equals = line.iter().position(|byte| *byte == SEPARATOR)?;
-    if &line[1..equals] != name.as_bytes() {
+    let name = &line[1..equals];
+    if name.is_empty() || !name.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'_') {
         return None;
     }
-    std::str::from_utf8(&line[equals + 1..line.len() - 1]).ok()
+    Some((
+        std::str::from_utf8(name).ok()?,
+        std::str::from_utf8(&line[equals + 1..line.len() - 1]).ok()?,
+    ))
 }
 
 #[cfg(test)]
@@ -63,12 +103,14 @@ mod tests {
     use crate::index::Sanitized;
 
     #[test]
-    fn test_extract_variable() {
-        assert_eq!(None, extract_variable(b"[foo=bar", "foo"));
-        assert_eq!(None, extract_variable(b"foo=bar]", "foo"));
-        assert_eq!(None, extract_variable(b"[foo]", "foo"));
-        assert_eq!(None, extract_variable(b"[baz=bar]", "foo"));
-        assert_eq!(Some("bar"), extract_variable(b"[foo=bar]", "foo"));
+    fn test_parse_variable() {
+        assert_eq!(None, parse_variable(b"[foo=bar"));
+        assert_eq!(None, parse_variable(b"foo=bar]"));
+        assert_eq!(None, parse_variable(b"[foo]"));
+        assert_eq!(None, parse_variable(b"[=bar]"));
+        assert_eq!(None, parse_variable(b"[foo bar=baz]"));
+        assert_eq!(Some(("foo", "bar")), parse_variable(b"[foo=bar]"));
+        assert_eq!(Some(("CI_IMAGE", "a=b")), parse_variable(b"[CI_IMAGE=a=b]"));
     }
 
     #[test]
@@ -82,14 +124,33 @@ mod tests {
             Sanitized("quux"),
             Sanitized("[CI_JOB_DOC_URL=https://github.com/rust-lang/rust/job1]"),
             Sanitized("foobar"),
+            Sanitized("[CI_RUNNER_OS=linux]"),
+            Sanitized("[CI_JOB_NAME=other-job]"),
         ];
 
         let extracted = LogVariables::extract(LOG);
-        assert_eq!(Some("test-job"), extracted.job_name);
-        assert_eq!(Some("123"), extracted.pr_number);
+        assert_eq!(Some("test-job"), extracted.job_name());
+        assert_eq!(Some("123"), extracted.pr_number());
         assert_eq!(
             Some("https://github.com/rust-lang/rust/job1"),
-            extracted.doc_url
+            extracted.doc_url()
         );
+        assert_eq!(Some("linux"), extracted.get("CI_RUNNER_OS"));
+        assert_eq!(None, extracted.get("CI_IMAGE"));
+    }
+
+    #[test]
+    fn test_log_variables_limit() {
+        let mut variables = LogVariables::with_wanted(&["CI_IMAGE".to_string()]);
+        for i in 0..MAX_VARIABLES {
+            variables.observe(&Sanitized(format!("[x{i}=y]")));
+        }
+        variables.observe(&Sanitized("[CI_RUNNER_OS=linux]"));
+        variables.observe(&Sanitized("[CI_PR_NUMBER=123]"));
+        variables.observe(&Sanitized("[CI_IMAGE=dist-x86_64-linux]"));
+
+        assert_eq!(None, variables.get("CI_RUNNER_OS"));
+        assert_eq!(Some("123"), variables.pr_number());
+        assert_eq!(Some("dist-x86_64-linux"), variables.get("CI_IMAGE"));
     }
 }
@@ -0,0 +1,171 @@
+use regex::bytes::Regex;
+use std::borrow::Cow;
+use std::path::Path;
+
+/// Hashes have at least this many hexadecimal digits, as abbreviated by git.
+const MIN_HASH_LENGTH: usize = 7;
+
+/// Built-in rules, applied in order. The placeholders can refer to the groups captured by the
+/// pattern, e.g. `$1`.
+fn builtin_rules() -> Vec<(String, &'static str)> {
+    vec![
+        (
+            "\\b[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}\\b".into(),
+            "<uuid>",
+        ),
+        (
+            "(?:/tmp|/var/folders|(?i:[a-z]:\\\\Users\\\\[^\\\\]+\\\\AppData\\\\Local\\\\Temp))[/\\\\][^\\s:'\"`,;()\\[\\]{}<>]*".into(),
+            "<tmp>",
+        ),
+        (hash_pattern(), "<hash>"),
+        (
+            "\\b[0-9]+(?:\\.[0-9]+)?(?:ns|µs|us|ms|s|m|h)\\b".into(),
+            "<duration>",
+        ),
+        (
+            "\\b(localhost|[0-9]{1,3}(?:\\.[0-9]{1,3}){3}|\\[::1?\\]):[0-9]{1,5}\\b".into(),
+            "$1:<port>",
+        ),
+        ("\\b(pid|PID|process)([ =:]+)[0-9]+\\b".into(), "$1$2<pid>"),
+    ]
+}
+
+/// Matches the lowercase hexadecimal words of at least `MIN_HASH_LENGTH` characters containing
+/// both digits and letters, so that numbers and words like `defaced` are left alone. The regex
+/// engine has no lookahead, so there's an alternative for each length of the leading run of
+/// digits or letters, requiring enough characters after it.
+fn hash_pattern() -> String {
+    let alternatives = (1..MIN_HASH_LENGTH)
+        .map(|run| {
+            let (run, rest) = if run == MIN_HASH_LENGTH - 1 {
+                (format!("{run},"), 0)
+            } else {
+                (run.to_string(), MIN_HASH_LENGTH - run - 1)
+            };
+            format!("[0-9]{{{run}}}[a-f][0-9a-f]{{{rest},}}|[a-f]{{{run}}}[0-9][0-9a-f]{{{rest},}}")
+        })
+        .collect::<Vec<_>>();
+    format!("\\b(?:{})\\b", alternatives.join("|"))
+}
+
+/// A pattern replaced by a placeholder when normalizing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    pub pattern: String,
+    pub placeholder: String,
+}
+
+/// Replaces the parts of the sanitized lines which change between builds without changing the
+/// meaning of the line (commit hashes, temporary directories, durations, ...) with placeholders,
+/// so they don't make benign lines look unusual.
+///
+/// The index records the rules it was built with, as the lines must be normalized the same way
+/// when learning and when extracting.
+#[derive(Debug, Clone)]
+pub struct Normalizer {
+    rules: Vec<Rule>,
+    regexes: Vec<Regex>,
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Normalizer::new(
+            builtin_rules()
+                .into_iter()
+                .map(|(pattern, placeholder)| Rule {
+                    pattern,
+                    placeholder: placeholder.into(),
+                })
+                .collect(),
+        )
+        .unwrap()
+    }
+}
+
+impl PartialEq for Normalizer {
+    fn eq(&self, other: &Self) -> bool {
+        self.rules == other.rules
+    }
+}
+
+impl Normalizer {
+    pub fn new(rules: Vec<Rule>) -> crate::Result<Self> {
+        let regexes = rules
+            .iter()
+            .map(|rule| Regex::new(&rule.pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Normalizer { rules, regexes })
+    }
+
+    /// A normalizer leaving the lines untouched, as used by indexes predating normalization.
+    pub fn none() -> Self {
+        Normalizer {
+            rules: vec![],
+            regexes: vec![],
+        }
+    }
+
+    /// The built-in rules, preceded by the ones defined in the given JSON file. The file contains
+    /// a list of objects, each with a `pattern` regex and the `placeholder` replacing it.
+    pub fn load(path: &Path) -> crate::Result<Self> {
+        let mut rules: Vec<Rule> = serde_json::from_slice(&std::fs::read(path)?)?;
+        rules.extend(Normalizer::default().rules);
+        Normalizer::new(rules)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    pub fn normalize<'a>(&self, line: &'a [u8]) -> Cow<'a, [u8]> {
+        let mut line = Cow::Borrowed(line);
+        for (regex, rule) in self.regexes.iter().zip(&self.rules) {
+            if let Cow::Owned(replaced) = regex.replace_all(&line, rule.placeholder.as_bytes()) {
+                line = Cow::Owned(replaced);
+            }
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(line: &str) -> String {
+        let normalizer = Normalizer::default();
+        String::from_utf8(normalizer.normalize(line.as_bytes()).into_owned()).unwrap()
+    }
+
+    #[test]
+    fn test_builtin_rules() {
+        assert_eq!(
+            normalize("test result: ok. 12 passed; 0 failed; finished in 12.34s"),
+            "test result: ok. 12 passed; 0 failed; finished in <duration>"
+        );
+        assert_eq!(
+            normalize("Compiling foo v0.1.0 (/tmp/.tmpAbC123/foo)"),
+            "Compiling foo v0.1.0 (<tmp>)"
+        );
+        assert_eq!(
+            normalize("HEAD is now at 3f2a9b1c0d Merge a4b0c1f2-1d2e-4f5a-8b9c-0d1e2f3a4b5c"),
+            "HEAD is now at <hash> Merge <uuid>"
+        );
+        assert_eq!(
+            normalize("listening on 127.0.0.1:41235, pid 4242"),
+            "listening on 127.0.0.1:<port>, pid <pid>"
+        );
+        assert_eq!(normalize("error: decaf coffee"), "error: decaf coffee");
+        assert_eq!(normalize("defaced 1234567 bytes"), "defaced 1234567 bytes");
+        assert_eq!(
+            normalize("1234567a abcdef1 d3adb33f 1a2b3c"),
+            "<hash> <hash> <hash> 1a2b3c"
+        );
+    }
+
+    #[test]
+    fn test_normalizer_equality() {
+        assert!(Normalizer::default() == Normalizer::default());
+        assert!(Normalizer::default() != Normalizer::none());
+    }
+}
@@ -1,42 +1,375 @@
 use crate::ci::CiPlatform;
-use regex::bytes::Regex;
+use crate::index::IndexData;
+use std::borrow::Cow;
+use std::io::{self, BufRead};
 
-pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
-    lazy_static! {
-        static ref LINE_BREAK: Regex = Regex::new("[\\r\\n]").unwrap();
+/// A line of a log, keeping track of where it came from.
+pub struct LogLine<'a> {
+    /// The 1-based number of the line in the log.
+    pub number: usize,
+    pub original: &'a [u8],
+    pub sanitized: Cow<'a, [u8]>,
+}
+
+impl IndexData for LogLine<'_> {
+    fn sanitized(&self) -> &[u8] {
+        &self.sanitized
     }
 
-    LINE_BREAK
-        .split(data)
-        .filter(|line| !line.iter().all(|b| b.is_ascii_whitespace()))
+    fn original(&self) -> &[u8] {
+        self.original
+    }
+}
+
+/// Splits the log into lines and [`clean`]s each of them, leaving out the blank lines.
+pub fn load_lines<'a>(ci: &dyn CiPlatform, data: &'a [u8]) -> Vec<LogLine<'a>> {
+    data.split(|&b| b == b'\n')
+        .enumerate()
+        .filter_map(|(i, line)| {
+            Some(LogLine {
+                number: i + 1,
+                original: line,
+                sanitized: clean_visible(ci, line)?,
+            })
+        })
         .collect()
 }
 
+/// A [`LogLine`] owning its data, as returned by [`read_lines`].
+pub struct OwnedLogLine {
+    /// The 1-based number of the line in the log.
+    pub number: usize,
+    pub original: Vec<u8>,
+    pub sanitized: Vec<u8>,
+}
+
+impl IndexData for OwnedLogLine {
+    fn sanitized(&self) -> &[u8] {
+        &self.sanitized
+    }
+
+    fn original(&self) -> &[u8] {
+        &self.original
+    }
+}
+
+/// Like [`load_lines`], but reads the log incrementally instead of requiring all of it in memory.
+pub fn read_lines<R: BufRead>(ci: &dyn CiPlatform, reader: R) -> ReadLines<'_, R> {
+    ReadLines {
+        ci,
+        reader,
+        number: 0,
+        buf: vec![],
+    }
+}
+
+/// Iterator over the lines of a log, see [`read_lines`].
+pub struct ReadLines<'a, R: BufRead> {
+    ci: &'a dyn CiPlatform,
+    reader: R,
+    number: usize,
+    buf: Vec<u8>,
+}
+
+impl<R: BufRead> Iterator for ReadLines<'_, R> {
+    type Item = io::Result<OwnedLogLine>;
+
+    fn next(&mut self) -> Option<io::Result<OwnedLogLine>> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_until(b'\n', &mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+            if self.buf.last() == Some(&b'\n') {
+                self.buf.pop();
+            }
+            self.number += 1;
+
+            if let Some(sanitized) = clean_visible(self.ci, &self.buf) {
+                return Some(Ok(OwnedLogLine {
+                    number: self.number,
+                    original: self.buf.clone(),
+                    sanitized: sanitized.into_owned(),
+                }));
+            }
+        }
+    }
+}
+
+pub fn split_lines(data: &[u8]) -> Vec<&[u8]> {
+    split_numbered_lines(data)
+        .into_iter()
+        .map(|(_, line)| line)
+        .collect()
+}
+
+/// Like [`split_lines`], but also returns the 1-based number of the line each returned line comes
+/// from.
+///
+/// Only `\n` is considered a line break: the `\r` used by progress bars to redraw a line are
+/// handled by [`render_terminal`], so only the final version of the line is kept. Only the lines
+/// made of whitespace are left out, the lines which are blank once rendered (e.g. erased progress
+/// bars) are left out by [`load_lines`] and [`read_lines`], which render each line only once.
+pub fn split_numbered_lines(data: &[u8]) -> Vec<(usize, &[u8])> {
+    data.split(|&b| b == b'\n')
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| !line.iter().all(|b| b.is_ascii_whitespace()))
+        .collect()
+}
+
+/// [`clean`]s the `line`, or returns `None` if nothing but whitespace would be visible when
+/// printing it in a terminal.
+fn clean_visible<'a>(ci: &dyn CiPlatform, line: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+    let sanitized = clean(ci, line);
+    if sanitized.iter().all(|b| b.is_ascii_whitespace()) {
+        None
+    } else {
+        Some(sanitized)
+    }
+}
+
 /// Cleans up the given `data`:
 ///
-/// * Removes most ANSI escape codes from the input.
+/// * Renders it like a terminal would, see [`render_terminal`].
 /// * Replaces all (Unicode) whitespace with single spaces.
 /// * Removes all (Unicode) control characters.
-pub fn clean(ci: &dyn CiPlatform, data: &[u8]) -> Vec<u8> {
-    lazy_static! {
-        /// This catches most escape sequences. And I care about neither
-        ///
-        /// * the very-special cases ("Set Keyboard Strings") that aren't matched properly nor
-        /// * legitimate output (which hopefully doesn't exist) that contains `ESC`.
-        ///
-        /// Reference: http://ascii-table.com/ansi-escape-sequences.php
-        static ref ANSI_ESCAPES: Regex = Regex::new("\x1b.*?[a-zA-Z]").unwrap();
+///
+/// The `data` is only copied if it needs to be changed.
+pub fn clean<'a>(ci: &dyn CiPlatform, data: &'a [u8]) -> Cow<'a, [u8]> {
+    let data = ci.remove_timestamp_from_log_line(data);
+    let data = and_then(data, render_terminal);
+    and_then(data, clean_characters)
+}
 
-        static ref UNICODE_WHITESPACE: Regex = Regex::new("(?u:\\p{White_Space})").unwrap();
+/// Applies `f` to `data`, only keeping the copy made by `f` if it changed something.
+fn and_then<'a>(data: Cow<'a, [u8]>, f: impl Fn(&[u8]) -> Cow<'_, [u8]>) -> Cow<'a, [u8]> {
+    match data {
+        Cow::Borrowed(data) => f(data),
+        Cow::Owned(data) => {
+            let changed = match f(&data) {
+                Cow::Owned(changed) => Some(changed),
+                Cow::Borrowed(_) => None,
+            };
+            Cow::Owned(changed.unwrap_or(data))
+        }
+    }
+}
+
+/// Replaces the whitespace with spaces and removes the control characters of `data` in a single
+/// pass. Bytes which are not valid UTF-8 are left untouched.
+fn clean_characters(data: &[u8]) -> Cow<'_, [u8]> {
+    // Created when the first character needing a change is found.
+    let mut cleaned: Option<Vec<u8>> = None;
 
-        static ref UNICODE_CONTROL: Regex = Regex::new("(?u:\\p{Control})").unwrap();
+    let mut i = 0;
+    while i < data.len() {
+        let (len, replacement): (usize, Option<&[u8]>) = match data[i] {
+            b'\t' | b'\n' | 0x0b | 0x0c | b'\r' => (1, Some(SPACE)),
+            0x00..=0x1f | 0x7f => (1, Some(b"")),
+            0x20..=0x7e => (1, None),
+            _ => {
+                let len = utf8_len(&data[i..]);
+                let c = std::str::from_utf8(&data[i..i + len])
+                    .ok()
+                    .and_then(|c| c.chars().next());
+                match c {
+                    Some(c) if c.is_whitespace() => (len, Some(SPACE)),
+                    Some(c) if c.is_control() => (len, Some(b"")),
+                    _ => (len, None),
+                }
+            }
+        };
+
+        match (replacement, &mut cleaned) {
+            (Some(replacement), None) => {
+                let mut copy = Vec::with_capacity(data.len());
+                copy.extend_from_slice(&data[..i]);
+                copy.extend_from_slice(replacement);
+                cleaned = Some(copy);
+            }
+            (Some(replacement), Some(cleaned)) => cleaned.extend_from_slice(replacement),
+            (None, Some(cleaned)) => cleaned.extend_from_slice(&data[i..i + len]),
+            (None, None) => {}
+        }
+        i += len;
     }
 
-    let data = ci.remove_timestamp_from_log_line(data);
+    match cleaned {
+        Some(cleaned) => Cow::Owned(cleaned),
+        None => Cow::Borrowed(data),
+    }
+}
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+const BACKSPACE: u8 = 0x08;
+const SPACE: &[u8] = b" ";
+/// Column past which the escape sequences can't move the cursor, so that a log can't make a
+/// rendered line arbitrarily long. Lines this long are elided as garbage by default anyway.
+const MAX_COLUMN: usize = 4096;
+
+/// Renders a line of the log the way a terminal would display it, leaving everything else
+/// (timestamps, indentation, etc.) untouched:
+///
+/// * ANSI escape sequences (CSI, OSC, charset designations, ...) are removed. Malformed sequences
+///   are dropped up to the first byte which can't be part of them, instead of swallowing the rest of
+///   the line.
+/// * `\r` and backspaces move the cursor back, so the text printed afterwards overwrites the
+///   previous one, as progress bars do. The sequences erasing the line or moving the cursor
+///   horizontally are honored as well.
+pub fn render_terminal(data: &[u8]) -> Cow<'_, [u8]> {
+    if !data
+        .iter()
+        .any(|&b| b == ESC || b == b'\r' || b == BACKSPACE)
+    {
+        return Cow::Borrowed(data);
+    }
+
+    let mut screen = Screen::default();
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            ESC => i = screen.escape(data, i),
+            b'\r' => {
+                screen.cursor = 0;
+                i += 1;
+            }
+            BACKSPACE => {
+                screen.cursor = screen.cursor.saturating_sub(1);
+                i += 1;
+            }
+            _ => {
+                let len = utf8_len(&data[i..]);
+                screen.print(&data[i..i + len]);
+                i += len;
+            }
+        }
+    }
 
-    let data = ANSI_ESCAPES.replace_all(data.as_ref(), b"".as_ref());
-    let data = UNICODE_WHITESPACE.replace_all(&data, b" ".as_ref());
-    UNICODE_CONTROL
-        .replace_all(&data, b"".as_ref())
-        .into_owned()
+    Cow::Owned(screen.cells.concat())
+}
+
+/// The line being rendered by [`render_terminal`], each cell holding a character.
+#[derive(Default)]
+struct Screen<'a> {
+    cells: Vec<&'a [u8]>,
+    cursor: usize,
+}
+
+impl<'a> Screen<'a> {
+    fn print(&mut self, c: &'a [u8]) {
+        self.move_to(self.cursor);
+        if self.cursor < self.cells.len() {
+            self.cells[self.cursor] = c;
+        } else {
+            self.cells.push(c);
+        }
+        self.cursor += 1;
+    }
+
+    /// Moves the cursor, filling the line with spaces if it's moved past its end.
+    fn move_to(&mut self, cursor: usize) {
+        let cursor = cursor.min(MAX_COLUMN);
+        if self.cells.len() < cursor {
+            self.cells.resize(cursor, SPACE);
+        }
+        self.cursor = cursor;
+    }
+
+    /// Handles the escape sequence starting at `start`, returning the index following it.
+    fn escape(&mut self, data: &[u8], start: usize) -> usize {
+        let Some(&kind) = data.get(start + 1) else {
+            return data.len();
+        };
+        match kind {
+            // Control Sequence Introducer: parameters, intermediate bytes and a final byte.
+            b'[' => {
+                let params_start = start + 2;
+                let params_end = skip(data, params_start, 0x30..=0x3f);
+                let end = skip(data, params_end, 0x20..=0x2f);
+                match data.get(end) {
+                    Some(&function @ 0x40..=0x7e) => {
+                        self.control(function, &data[params_start..params_end]);
+                        end + 1
+                    }
+                    _ => end,
+                }
+            }
+            // Operating System Command and the other strings, terminated by BEL or `ESC \`.
+            b']' | b'P' | b'X' | b'^' | b'_' => {
+                let mut i = start + 2;
+                while i < data.len() {
+                    match data[i] {
+                        BEL => return i + 1,
+                        ESC if data.get(i + 1) == Some(&b'\\') => return i + 2,
+                        _ => i += 1,
+                    }
+                }
+                // Unterminated, keep the text in case it was not meant to be a command.
+                start + 2
+            }
+            // Intermediate bytes followed by a final byte, e.g. `ESC ( B` to select a charset.
+            0x20..=0x2f => {
+                let end = skip(data, start + 1, 0x20..=0x2f);
+                match data.get(end) {
+                    Some(0x30..=0x7e) => end + 1,
+                    _ => end,
+                }
+            }
+            0x30..=0x7e => start + 2,
+            _ => start + 1,
+        }
+    }
+
+    /// Applies the control sequence ending with `function`, ignoring the ones which don't change
+    /// the text of the line (e.g. colors).
+    fn control(&mut self, function: u8, params: &[u8]) {
+        let param = std::str::from_utf8(params)
+            .ok()
+            .and_then(|params| params.split(';').next())
+            .and_then(|param| param.parse::<usize>().ok());
+        match function {
+            // Erase in line
+            b'K' => match param.unwrap_or(0) {
+                0 => self.cells.truncate(self.cursor),
+                1 => {
+                    let end = self.cells.len().min(self.cursor + 1);
+                    self.cells[..end].fill(SPACE);
+                }
+                _ => self.cells.clear(),
+            },
+            // Cursor forward, backward and horizontal absolute
+            b'C' => self.move_to(self.cursor.saturating_add(param.unwrap_or(1).max(1))),
+            b'D' => self.cursor = self.cursor.saturating_sub(param.unwrap_or(1).max(1)),
+            b'G' => self.move_to(param.unwrap_or(1).max(1) - 1),
+            _ => {}
+        }
+    }
+}
+
+/// Returns the index of the first byte from `start` which is not in `range`.
+fn skip(data: &[u8], start: usize, range: std::ops::RangeInclusive<u8>) -> usize {
+    start
+        + data[start.min(data.len())..]
+            .iter()
+            .take_while(|b| range.contains(b))
+            .count()
+}
+
+/// Length of the UTF-8 character at the start of `data`, or 1 if it's not valid UTF-8.
+fn utf8_len(data: &[u8]) -> usize {
+    let len = match data[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    if data.len() >= len && std::str::from_utf8(&data[..len]).is_ok() {
+        len
+    } else {
+        1
+    }
 }
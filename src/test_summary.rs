@@ -0,0 +1,93 @@
+use crate::index::IndexData;
+
+/// Header of the list of failed tests libtest and compiletest print at the end of a test suite.
+/// The same header also precedes the output of the failed tests.
+const FAILURES_HEADER: &[u8] = b"failures:";
+/// Indentation of the test names in the list.
+const INDENT: &[u8] = b"    ";
+
+/// Collects the names of the failed tests from the summaries printed by libtest and compiletest,
+/// e.g.
+///
+/// ```text
+/// failures:
+///     [ui] tests/ui/foo.rs
+///     tests::it_works
+///
+/// test result: FAILED. 1 passed; 2 failed; 0 ignored; 0 measured; 0 filtered out
+/// ```
+///
+/// The summaries are usually far from the lines that get extracted, so all the lines of the log
+/// are expected to be [`observe`](FailedTests::observe)d.
+#[derive(Default)]
+pub struct FailedTests {
+    names: Vec<String>,
+    in_summary: bool,
+}
+
+impl FailedTests {
+    pub fn extract<I: IndexData>(lines: &[I]) -> Self {
+        let mut result = FailedTests::default();
+        for line in lines {
+            result.observe(line);
+        }
+        result
+    }
+
+    pub fn observe<I: IndexData>(&mut self, line: &I) {
+        let sanitized = line.sanitized();
+
+        if sanitized.trim_ascii() == FAILURES_HEADER {
+            self.in_summary = true;
+        } else if self.in_summary {
+            match sanitized.strip_prefix(INDENT) {
+                Some(name) if !name.starts_with(b" ") && !name.starts_with(b"-") => {
+                    let name = String::from_utf8_lossy(name.trim_ascii()).into_owned();
+                    if !self.names.contains(&name) {
+                        self.names.push(name);
+                    }
+                }
+                _ => self.in_summary = false,
+            }
+        }
+    }
+
+    /// The names of the failed tests, in the order they were reported.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Sanitized;
+
+    #[test]
+    fn test_failed_tests() {
+        let lines = [
+            "failures:",
+            "---- tests::it_works stdout ----",
+            "thread 'tests::it_works' panicked at src/lib.rs:12:9:",
+            "    left: 1",
+            "failures:",
+            "    tests::it_works",
+            "    tests::other",
+            "test result: FAILED. 1 passed; 2 failed; 0 ignored; 0 measured; 0 filtered out",
+            "    not::a::test",
+            "failures:",
+            "    [ui] tests/ui/foo.rs#edition2021",
+            "    tests::it_works",
+        ];
+        let failed = FailedTests::extract(&lines.iter().map(Sanitized).collect::<Vec<_>>());
+
+        assert_eq!(
+            failed.names(),
+            &[
+                "tests::it_works",
+                "tests::other",
+                "[ui] tests/ui/foo.rs#edition2021"
+            ]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! GitHub Actions [workflow commands], both as emitted by the steps (`::error file=x::message`)
+//! and as rendered in the downloaded logs (`##[error]message`).
+//!
+//! [workflow commands]: https://docs.github.com/en/actions/using-workflows/workflow-commands-for-github-actions
+
+use crate::index::IndexData;
+use std::fmt;
+
+/// Maximum number of annotations remembered for a single log.
+const MAX_ANNOTATIONS: usize = 100;
+
+/// Annotations added by GitHub Actions itself to every failed job, which don't say anything about
+/// the cause of the failure.
+static GENERIC_MESSAGES: &[&str] = &["Process completed with exit code"];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Error,
+    Warning,
+    Notice,
+}
+
+/// A message attached to a file or to the whole job by an `error`, `warning` or `notice` command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Annotation {
+    pub level: Level,
+    pub message: String,
+    pub title: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+    pub end_line: Option<u32>,
+    pub column: Option<u32>,
+    pub end_column: Option<u32>,
+    /// The group (usually the step) in which the annotation was emitted.
+    pub group: Option<String>,
+}
+
+impl Annotation {
+    /// Whether the annotation is added to all the failed jobs, regardless of the failure.
+    pub fn is_generic(&self) -> bool {
+        GENERIC_MESSAGES
+            .iter()
+            .any(|generic| self.message.starts_with(generic))
+    }
+}
+
+impl fmt::Display for Annotation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.level {
+            Level::Error => f.write_str("error")?,
+            Level::Warning => f.write_str("warning")?,
+            Level::Notice => f.write_str("notice")?,
+        }
+        if let Some(ref file) = self.file {
+            write!(f, " at {}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+            }
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+        }
+        if let Some(ref title) = self.title {
+            write!(f, ": {}", title)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// A workflow command found in a line of the log.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    Annotation(Annotation),
+    Group(String),
+    EndGroup,
+}
+
+/// Parses the workflow command in `line`, if any. Commands other than annotations and groups are
+/// ignored.
+pub fn parse(line: &[u8]) -> Option<Command> {
+    let line = std::str::from_utf8(line).ok()?.trim_start();
+
+    let (name, rest) = if let Some(rendered) = line.strip_prefix("##[") {
+        rendered.split_once(']')?
+    } else {
+        line.strip_prefix("::")?
+            .split_once([' ', ':'])
+            .map(|(name, _)| (name, &line[2 + name.len()..]))?
+    };
+
+    // The properties are separated from the message by `::`, both when emitted and when rendered.
+    let (properties, message) = match rest.split_once("::") {
+        Some((properties, message)) if is_properties(properties.trim()) => {
+            (properties.trim(), message)
+        }
+        Some(("", message)) => ("", message),
+        _ if line.starts_with("::") => return None,
+        _ => ("", rest),
+    };
+
+    let level = match name {
+        "error" => Level::Error,
+        "warning" => Level::Warning,
+        "notice" => Level::Notice,
+        "group" => return Some(Command::Group(unescape_data(message))),
+        "endgroup" => return Some(Command::EndGroup),
+        _ => return None,
+    };
+
+    let mut annotation = Annotation {
+        level,
+        message: unescape_data(message),
+        title: None,
+        file: None,
+        line: None,
+        end_line: None,
+        column: None,
+        end_column: None,
+        group: None,
+    };
+    for (key, value) in properties.split(',').filter_map(|p| p.split_once('=')) {
+        let value = unescape_property(value);
+        match key.trim() {
+            "title" => annotation.title = Some(value),
+            "file" => annotation.file = Some(value),
+            "line" => annotation.line = value.parse().ok(),
+            "endLine" => annotation.end_line = value.parse().ok(),
+            "col" => annotation.column = value.parse().ok(),
+            "endColumn" => annotation.end_column = value.parse().ok(),
+            _ => {}
+        }
+    }
+    Some(Command::Annotation(annotation))
+}
+
+/// Whether `properties` looks like a list of `key=value` properties.
+fn is_properties(properties: &str) -> bool {
+    !properties.is_empty()
+        && properties.split(',').all(|property| {
+            property
+                .split_once('=')
+                .is_some_and(|(key, _)| !key.is_empty() && key.chars().all(char::is_alphanumeric))
+        })
+}
+
+fn unescape_data(data: &str) -> String {
+    data.replace("%0D", "\r")
+        .replace("%0A", "\n")
+        .replace("%25", "%")
+}
+
+fn unescape_property(property: &str) -> String {
+    unescape_data(&property.replace("%3A", ":").replace("%2C", ","))
+}
+
+/// Collects the annotations of a log, keeping track of the group they were emitted in.
+#[derive(Default)]
+pub struct WorkflowCommands {
+    annotations: Vec<Annotation>,
+    group: Option<String>,
+}
+
+impl WorkflowCommands {
+    pub fn extract<I: IndexData>(lines: &[I]) -> Self {
+        let mut result = WorkflowCommands::default();
+        for line in lines {
+            result.observe(line);
+        }
+        result
+    }
+
+    /// Records the command in `line`, for logs which are read incrementally.
+    pub fn observe<I: IndexData>(&mut self, line: &I) {
+        match parse(line.sanitized()) {
+            Some(Command::Annotation(mut annotation))
+                if self.annotations.len() < MAX_ANNOTATIONS =>
+            {
+                annotation.group = self.group.clone();
+                self.annotations.push(annotation);
+            }
+            Some(Command::Group(name)) => self.group = Some(name),
+            Some(Command::EndGroup) => self.group = None,
+            Some(Command::Annotation(_)) | None => {}
+        }
+    }
+
+    /// The annotations, in the order they appear in the log.
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    pub fn into_annotations(self) -> Vec<Annotation> {
+        self.annotations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::Sanitized;
+
+    fn annotation(line: &str) -> Annotation {
+        match parse(line.as_bytes()) {
+            Some(Command::Annotation(annotation)) => annotation,
+            other => panic!("not an annotation: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let error = annotation("##[error]Process completed with exit code 1.");
+        assert_eq!(error.level, Level::Error);
+        assert_eq!(error.message, "Process completed with exit code 1.");
+        assert!(error.is_generic());
+
+        let error = annotation("##[error]file=src/lib.rs,line=10,col=5::mismatched types");
+        assert_eq!(
+            error.to_string(),
+            "error at src/lib.rs:10:5: mismatched types"
+        );
+
+        let warning = annotation(
+            "::warning file=src/a%2Cb.rs,line=3,endLine=4,title=Unused%3A x::unused variable%0Ahelp",
+        );
+        assert_eq!(warning.level, Level::Warning);
+        assert_eq!(warning.file.as_deref(), Some("src/a,b.rs"));
+        assert_eq!(warning.end_line, Some(4));
+        assert_eq!(warning.title.as_deref(), Some("Unused: x"));
+        assert_eq!(warning.message, "unused variable\nhelp");
+
+        assert_eq!(annotation("::notice::all good").message, "all good");
+        assert_eq!(
+            annotation("##[warning]see https://example.com::8080").message,
+            "see https://example.com::8080"
+        );
+
+        assert_eq!(
+            parse(b"##[group]Run cargo test"),
+            Some(Command::Group("Run cargo test".into()))
+        );
+        assert_eq!(parse(b"##[endgroup]"), Some(Command::EndGroup));
+        assert_eq!(parse(b"##[command]/usr/bin/git log"), None);
+        assert_eq!(parse(b"::error but not a command"), None);
+        assert_eq!(parse(b"error: oops"), None);
+    }
+
+    #[test]
+    fn test_groups() {
+        let lines = [
+            "##[group]Run src/ci/scripts/run-build.sh",
+            "##[error]tidy failed",
+            "##[endgroup]",
+            "##[error]Process completed with exit code 1.",
+        ];
+        let commands = WorkflowCommands::extract(&lines.iter().map(Sanitized).collect::<Vec<_>>());
+
+        let groups = commands
+            .annotations()
+            .iter()
+            .map(|a| a.group.as_deref())
+            .collect::<Vec<_>>();
+        assert_eq!(groups, vec![Some("Run src/ci/scripts/run-build.sh"), None]);
+    }
+}
@@ -5,6 +5,18 @@ use std::path::Path;
 use rust_log_analyzer as rla;
 
 const TEST_LOCATION: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests");
+/// Base64 encoded data, as found in logs dumping binary files.
+const BLOB: &str = "H4sIAAAAAAAAA+1Ze3PbNhLP5/gUGN7MOZ2xJL5EStNJrxhbiZPYsU+Wm7S2x0ORkMWYIlWQsq/x+LvfAnxI+kOyJ1p0R1w9";
+
+/// A synthetic log in which each of the `unusual` lines is followed by enough common lines to be
+/// extracted in its own block.
+fn padded_log(unusual: &[&'static str]) -> Vec<rla::index::Sanitized<&'static str>> {
+    unusual
+        .iter()
+        .flat_map(|&line| std::iter::once(line).chain(std::iter::repeat_n("ok", 20)))
+        .map(rla::index::Sanitized)
+        .collect()
+}
 
 #[test]
 fn test_missing_line() {
@@ -63,3 +75,383 @@ shell: /usr/bin/bash --noprofile --norc -e -o pipefail {0}"###;
         .join("\n");
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn test_extract_ranked() {
+    let lines = padded_log(&[
+        "first unusual block of the log",
+        "error: the actual cause of the failure",
+        "second unusual block of the log",
+    ]);
+
+    let blocks = rla::extract::extract_ranked(
+        &rla::extract::Config::default(),
+        &rla::index::Index::default(),
+        &lines,
+    );
+
+    let ranking = blocks
+        .iter()
+        .map(|block| (block.position, block.anchor_hits))
+        .collect::<Vec<_>>();
+    assert_eq!(ranking, vec![(1, 1), (2, 0), (0, 0)]);
+    assert_eq!(
+        blocks[0].lines[4].0,
+        "error: the actual cause of the failure"
+    );
+}
+
+#[test]
+fn test_extraction_line_numbers() {
+    let log = b"ok\nok\r\n\nfirst\runusual line of the log\nok\nok\n";
+    let numbered = rla::sanitize::split_numbered_lines(log);
+    assert_eq!(
+        numbered.iter().map(|(n, _)| *n).collect::<Vec<_>>(),
+        vec![1, 2, 4, 5, 6]
+    );
+
+    let lines = numbered
+        .iter()
+        .map(|(_, l)| rla::index::Sanitized(rla::sanitize::render_terminal(l)))
+        .collect::<Vec<_>>();
+    let extraction = rla::extract::Extraction::extract(
+        &rla::extract::Config::default(),
+        &rla::index::Index::default(),
+        &lines,
+    );
+
+    assert_eq!(extraction.blocks.len(), 1);
+    let block = &extraction.blocks[0];
+    assert_eq!((block.start, block.end), (1, 5));
+    assert_eq!(block.scores, vec![0, 190, 0, 0]);
+    assert_eq!(
+        block.trigger,
+        rla::extract::Trigger::UniqueLine {
+            line: 2,
+            score: 190
+        }
+    );
+    assert_eq!(&*lines[2].0, b"unusual line of the log");
+    assert_eq!(numbered[2].0, 4);
+}
+
+#[test]
+fn test_line_output() {
+    use rla::extract::LineOutput;
+
+    let log = b"2024-01-01T00:00:00.0000000Z   \x1b[31merror\x1b[0m:  oops\n";
+    let lines = rla::sanitize::load_lines(&rla::ci::GitHubActions::new("DUMMY_TOKEN"), log);
+    assert_eq!(lines.len(), 1);
+
+    let garbage = rla::garbage::GarbageFilter::default();
+    let render =
+        |output: LineOutput| String::from_utf8(output.render(&lines[0], &garbage)).unwrap();
+    assert_eq!(render(LineOutput::Sanitized), "  error:  oops");
+    assert_eq!(
+        render(LineOutput::Original),
+        "2024-01-01T00:00:00.0000000Z   error:  oops"
+    );
+    assert_eq!(
+        render(LineOutput::Both),
+        "2024-01-01T00:00:00.0000000Z   error:  oops\n  error:  oops"
+    );
+}
+
+#[test]
+fn test_render_terminal() {
+    use rla::sanitize::render_terminal;
+
+    let render = |line: &[u8]| String::from_utf8(render_terminal(line).into_owned()).unwrap();
+
+    // rustc
+    assert_eq!(
+        render(
+            b"\x1b[0m\x1b[1m\x1b[38;5;9merror[E0308]\x1b[0m\x1b[0m\x1b[1m: mismatched types\x1b[0m"
+        ),
+        "error[E0308]: mismatched types"
+    );
+    assert_eq!(
+        render(
+            b"\x1b[0m  \x1b[0m\x1b[0m\x1b[1m\x1b[38;5;12m--> \x1b[0m\x1b[0msrc/lib.rs:10:5\x1b[0m"
+        ),
+        "  --> src/lib.rs:10:5"
+    );
+    // cargo, with its progress bar being erased before the next message
+    assert_eq!(
+        render(
+            b"\x1b[1m\x1b[36m    Building\x1b[0m [=====>     ] 12/80: foo, bar\r\x1b[K\x1b[1m\x1b[32m   Compiling\x1b[0m baz v0.1.0"
+        ),
+        "   Compiling baz v0.1.0"
+    );
+    // cargo hyperlinks
+    assert_eq!(
+        render(
+            b"see \x1b]8;;file:///checkout/src/lib.rs\x1b\\src/lib.rs\x1b]8;;\x1b\\ for details"
+        ),
+        "see src/lib.rs for details"
+    );
+    // progress rewritten without erasing the line
+    assert_eq!(render(b"10%\r 50%\r100%"), "100%");
+    assert_eq!(render(b"downloading...\rdone"), "doneloading...");
+    assert_eq!(render(b"ok\r"), "ok");
+    // charset designation and malformed sequences
+    assert_eq!(render(b"\x1b(Bplain\x1b[m text"), "plain text");
+    assert_eq!(render(b"before \x1b[ 1 after"), "before 1 after");
+    assert_eq!(render(b"trailing \x1b"), "trailing ");
+    // cursor movements far past the end of the line
+    for line in [&b"a\x1b[999999999Gb"[..], b"a\x1b[18446744073709551615Cb"] {
+        let rendered = render(line);
+        assert!(rendered.len() <= 4097, "{} bytes", rendered.len());
+        assert!(rendered.starts_with("a ") && rendered.ends_with(" b"));
+    }
+}
+
+#[test]
+fn test_progress_lines_are_merged() {
+    let ci = rla::ci::GitHubActions::new("DUMMY_TOKEN");
+    let log = b"2024-01-01T00:00:00.0000000Z start\n\
+        2024-01-01T00:00:01.0000000Z \x1b[K 1/3\r\x1b[K 2/3\r\x1b[K 3/3\r\x1b[K\n\
+        2024-01-01T00:00:01.5000000Z \n\
+        2024-01-01T00:00:02.0000000Z error: oops\n";
+
+    let loaded = rla::sanitize::load_lines(&ci, log);
+    let read = rla::sanitize::read_lines(&ci, &log[..])
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+
+    for lines in [
+        loaded
+            .iter()
+            .map(|line| (line.number, line.sanitized.to_vec()))
+            .collect::<Vec<_>>(),
+        read.into_iter()
+            .map(|line| (line.number, line.sanitized))
+            .collect(),
+    ] {
+        assert_eq!(
+            lines,
+            vec![(1, b"start".to_vec()), (4, b"error: oops".to_vec())]
+        );
+    }
+}
+
+#[test]
+fn test_streaming_extractor() {
+    let ci = rla::ci::GitHubActions::new("DUMMY_TOKEN");
+    let config = rla::extract::Config::default();
+    let read = |name: &str| std::fs::read(Path::new(TEST_LOCATION).join(name)).unwrap();
+
+    // Learned from a passed build, the failed build being the same log with errors inserted.
+    let mut index = rla::index::Index::default();
+    for line in rla::sanitize::read_lines(&ci, &read("test_missing_line.txt")[..]) {
+        index.learn(&line.unwrap(), 100);
+    }
+
+    // Extracted the same way before the extraction was made incremental.
+    let expected = r###"##[group]Run actions/checkout@v4
+with:
+  fetch-depth: 2
+  repository: rust-lang/rust
+error[E0425]: cannot find value `foo` in this scope
+ --> src/lib.rs:3:5
+  |
+3 |     foo
+  |     ^^^ not found in this scope
+  token: ***
+  ssh-strict: true
+  ssh-user: git
+  persist-credentials: true
+  clean: true
+---
+ * [new ref]           d7844ebeec0d4ff6b742a4f303ec63e2bfe74a0e -> pull/131375/merge
+##[endgroup]
+##[group]Determining the checkout info
+##[endgroup]
+thread 'main' panicked at src/main.rs:2:5:
+assertion `left == right` failed
+##[error]Process completed with exit code 101."###;
+
+    let log = read("test_failed_build.txt");
+    let lines = rla::sanitize::read_lines(&ci, &log[..]).map(Result::unwrap);
+    let blocks = rla::extract::StreamingExtractor::new(&config, &index)
+        .blocks(lines)
+        .collect::<Vec<_>>();
+    let actual = blocks
+        .iter()
+        .map(|block| {
+            block
+                .lines
+                .iter()
+                .map(|line| String::from_utf8_lossy(&line.sanitized).into_owned())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n---\n");
+    assert_eq!(actual, expected);
+    assert_eq!(
+        blocks
+            .iter()
+            .map(|block| (block.start, block.end))
+            .collect::<Vec<_>>(),
+        vec![(41, 55), (110, 117)]
+    );
+
+    // Blocks are returned as soon as they can't change anymore, before the end of the log.
+    let mut extractor = rla::extract::StreamingExtractor::new(&config, &index);
+    for line in padded_log(&["first unusual block of the log"]) {
+        extractor.push(line);
+    }
+    let block = extractor.pop_block().unwrap();
+    assert_eq!((block.start, block.end), (0, 6));
+    assert!(extractor.pop_block().is_none());
+}
+
+#[test]
+fn test_extract_with_baseline() {
+    let lines = padded_log(&[
+        "error: pre-existing failure",
+        "error: the actual cause of the failure",
+    ]);
+
+    let baseline = ["error: pre-existing failure", "ok"]
+        .iter()
+        .map(rla::index::Sanitized)
+        .collect::<rla::baseline::Baseline>();
+
+    let config = rla::extract::Config::default();
+    let index = rla::index::Index::default();
+    let blocks = rla::extract::StreamingExtractor::new(&config, &index)
+        .with_baseline(&baseline)
+        .blocks(&lines)
+        .collect::<Vec<_>>();
+
+    // The pre-existing failure is suppressed, only the new one is extracted.
+    assert_eq!(blocks.len(), 1);
+    assert_eq!((blocks[0].start, blocks[0].end), (17, 27));
+    assert_eq!(
+        blocks[0].lines[4].0,
+        "error: the actual cause of the failure"
+    );
+}
+
+#[test]
+fn test_index_normalization_header() {
+    use rla::index::{Index, IndexStorage, Sanitized};
+    use rla::normalize::Normalizer;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index");
+    let storage = IndexStorage::new(path.to_str().unwrap()).unwrap();
+
+    let mut index = Index::default();
+    index.learn(&Sanitized("finished in 1.23s"), 1);
+    index.save(&storage).unwrap();
+
+    let loaded = Index::load(&storage).unwrap();
+    assert!(*loaded.normalizer() == Normalizer::default());
+    assert_eq!(
+        loaded
+            .scores(&Sanitized("finished in 4.56s"))
+            .collect::<Vec<_>>(),
+        index
+            .scores(&Sanitized("finished in 1.23s"))
+            .collect::<Vec<_>>()
+    );
+    assert!(Index::load_or_create(&storage, Normalizer::none()).is_err());
+
+    // Indexes saved before the header was introduced only contain the table.
+    let mut table = fnv::FnvHashMap::<u32, u32>::default();
+    table.insert(42, 1);
+    std::fs::write(&path, bincode::serialize(&table).unwrap()).unwrap();
+    let legacy = Index::load(&storage).unwrap();
+    assert!(*legacy.normalizer() == Normalizer::none());
+    // Learning without explicit rules keeps the rules of the existing index.
+    let legacy = Index::load_or_default(&storage).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(*legacy.normalizer() == Normalizer::none());
+    assert!(*Index::load_or_default(&storage).unwrap().normalizer() == Normalizer::default());
+}
+
+#[test]
+fn test_index_merge() {
+    use rla::index::{Index, Sanitized};
+    use rla::normalize::Normalizer;
+
+    let mut shared = Index::default();
+    shared.learn(&Sanitized("Compiling core"), 1);
+
+    let mut local = Index::new(shared.normalizer().clone());
+    local.learn(&Sanitized("Compiling core"), 2);
+    local.learn(&Sanitized("Compiling std"), 1);
+    shared.merge(local).unwrap();
+
+    let mut expected = Index::default();
+    expected.learn(&Sanitized("Compiling core"), 3);
+    expected.learn(&Sanitized("Compiling std"), 1);
+    for line in ["Compiling core", "Compiling std", "Compiling alloc"] {
+        assert_eq!(
+            shared.scores(&Sanitized(line)).collect::<Vec<_>>(),
+            expected.scores(&Sanitized(line)).collect::<Vec<_>>()
+        );
+    }
+
+    assert!(shared.merge(Index::new(Normalizer::none())).is_err());
+}
+
+#[test]
+fn test_clean() {
+    use std::borrow::Cow;
+
+    let ci = rla::ci::GitHubActions::new("DUMMY_TOKEN");
+    let clean = |line: &'static [u8]| rla::sanitize::clean(&ci, line);
+
+    assert!(matches!(
+        clean(b"2024-01-01T00:00:00.0000000Z   Compiling foo v0.1.0"),
+        Cow::Borrowed(b"  Compiling foo v0.1.0")
+    ));
+    assert_eq!(
+        &*clean(
+            "2024-01-01T00:00:00.0000000Z a\tb\u{a0}c\u{2003}d\u{0}e\u{7f}f\u{85}g\u{9b}h"
+                .as_bytes()
+        ),
+        b"a b c def gh"
+    );
+    assert_eq!(
+        &*clean(b"2024-01-01T00:00:00.0000000Z invalid \xff\xfe utf-8\x01"),
+        b"invalid \xff\xfe utf-8"
+    );
+}
+
+#[test]
+fn test_garbage_lines_are_not_extracted() {
+    let lines = padded_log(&[BLOB]);
+
+    let config = rla::extract::Config::default();
+    let index = rla::index::Index::default();
+    assert!(rla::extract::extract(&config, &index, &lines).is_empty());
+
+    let config = rla::extract::Config {
+        garbage: rla::garbage::GarbageFilter::disabled(),
+        ..rla::extract::Config::default()
+    };
+    assert_eq!(rla::extract::extract(&config, &index, &lines).len(), 1);
+}
+
+#[test]
+fn test_garbage_lines_dont_split_blocks() {
+    let mut lines = vec![rla::index::Sanitized(
+        "error: the build script of `openssl-sys` failed",
+    )];
+    lines.extend((0..20).map(|_| rla::index::Sanitized(BLOB)));
+    lines.extend(padded_log(&[
+        "note: run with `RUST_BACKTRACE=1` to display a backtrace",
+    ]));
+
+    // The blob is longer than the distance at which blocks are merged.
+    let config = rla::extract::Config::default();
+    let blocks = rla::extract::extract(&config, &rla::index::Index::default(), &lines);
+    assert_eq!(blocks.len(), 1);
+    assert!(blocks[0][0].0.starts_with("error:"));
+    assert!(blocks[0][21].0.starts_with("note:"));
+}